@@ -0,0 +1,83 @@
+//! Cost of one tracker pass. Run with `cargo bench --bench tracker`.
+
+use std::time::Instant;
+
+use gamelunch::core::clock::Tick;
+use gamelunch::core::session::History;
+use gamelunch::core::tracker::Scanner;
+
+const TICKS: u32 = 50;
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    // warm up caches and open file handles
+    f();
+
+    let start = Instant::now();
+
+    for _ in 0..TICKS {
+        f();
+    }
+
+    let per_tick = start.elapsed() / TICKS;
+
+    println!(
+        "{:<32} {:>10.3} ms/tick",
+        name,
+        per_tick.as_secs_f64() * 1000.0
+    );
+}
+
+fn main() {
+    let mut system = sysinfo::System::new_all();
+
+    bench("refresh_all (old tracker)", || {
+        system.refresh_all();
+    });
+
+    let mut scanner = Scanner::default();
+
+    println!("{} processes", scanner.scan().len());
+
+    bench("Scanner::scan", || {
+        scanner.scan();
+    });
+
+    let mut history = History::default();
+    let seen: Vec<_> = scanner
+        .scan()
+        .into_iter()
+        .map(|process| (process.pid, process.key))
+        .collect();
+    let mut now = 0;
+
+    bench("History::observe", || {
+        now += 5;
+
+        let tick = Tick {
+            now,
+            credit: 5,
+            gap: false,
+        };
+
+        history.observe(tick, seen.iter().cloned());
+    });
+
+    let mut history = History::default();
+
+    bench("scan + observe", || {
+        now += 5;
+
+        let tick = Tick {
+            now,
+            credit: 5,
+            gap: false,
+        };
+
+        let seen = scanner
+            .scan()
+            .into_iter()
+            .map(|process| (process.pid, process.key));
+
+        history.observe(tick, seen);
+    });
+}
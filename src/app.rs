@@ -1,123 +1,839 @@
-use std::process::Child;
-
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-
-use sysinfo::System;
-
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{NaiveDate, Weekday};
+use uuid::Uuid;
+
+use crate::core::budget::{Alert, Budget, Budgets, Limit};
+use crate::core::control::{self, Command};
+use crate::core::data::{self, Data};
+use crate::core::detect::Plan;
+use crate::core::format::{format_date, format_duration, format_time};
+use crate::core::launcher::{self, join_args, split_args, Launcher};
+use crate::core::library::{Library, TRASH_DAYS};
+use crate::core::lock::{self, Lock};
+use crate::core::matching::MatchRule;
+use crate::core::restore::Snapshot;
+use crate::core::runner::{self, Runner, RunnerKind, WineConfig};
+use crate::core::scope::Filter;
+use crate::core::session::{now, COMPACT_DAYS};
+use crate::core::settings::{
+    PanicMode, Settings, StartupPage, Theme, TrackingScope, WorkingDirPolicy,
+};
+use crate::core::target::LaunchTarget;
+use crate::core::tracker::{Scanner, Tracker};
+
+use crate::core::triggers::{self, ClassHours, Triggers};
+use crate::core::wrappers::Wrapper;
 use crate::enums::Page;
 use crate::structs::Game;
 
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// How long the undo button shows after removing a game.
+const UNDO_TIME: Duration = Duration::from_secs(10);
+
+/// How long the launcher stays unlocked after the PIN is entered.
+const UNLOCK_TIME: Duration = Duration::from_secs(10 * 60);
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct GameLunch {
     page: Page,
-    games: Vec<Game>,
+
+    #[serde(skip)]
+    library: Library,
 
     game: Game,
+    target_kind: String,
     location: String,
+    rom_input: String,
+    rule_kind: String,
+    rule_value: String,
+    args_input: String,
+    env_key: String,
+    env_value: String,
+    working_dir_input: String,
+    prefix_input: String,
 
     status: String,
     launch_status: String,
 
     #[serde(skip)]
-    procs: Vec<Child>,
+    confirm_launch: Option<Uuid>,
+
+    #[serde(skip)]
+    confirm_delete_prefix: Option<Uuid>,
+
+    /// The trashed game to delete for good once confirmed.
+    #[serde(skip)]
+    confirm_discard: Option<Uuid>,
+
+    #[serde(skip)]
+    confirm_empty_trash: bool,
+
+    /// The game the Add Game page is editing.
+    #[serde(skip)]
+    editing: Option<Uuid>,
 
-    pub time: Arc<Mutex<HashMap<String, u64>>>,
+    /// The last removed game and when, for the undo button.
+    #[serde(skip)]
+    undo: Option<(Uuid, Instant)>,
 
     #[serde(skip)]
-    thread_spawned: bool,
+    tracker: Tracker,
 
     removed_values: Vec<String>,
+
+    #[serde(skip)]
+    settings: Settings,
+
+    allow_input: String,
+    tracking_status: String,
+
+    trigger_input: String,
+    class_name: String,
+    class_days: [bool; 7],
+    class_start: String,
+    class_end: String,
+    ics_path: String,
+    schedule_status: String,
+
+    wrapper_name: String,
+    wrapper_command: String,
+    wrapper_env: String,
+    wrapper_status: String,
+
+    runner_name: String,
+    runner_kind: RunnerKind,
+    runner_path: String,
+    runner_status: String,
+
+    data_dir_input: String,
+    data_dir_status: String,
+
+    /// When the PIN was last entered.
+    #[serde(skip)]
+    unlocked: Option<Instant>,
+
+    #[serde(skip)]
+    pin_input: String,
+
+    #[serde(skip)]
+    new_pin: String,
+
+    #[serde(skip)]
+    confirm_pin: String,
+
+    #[serde(skip)]
+    lock_status: String,
+
+    #[serde(skip)]
+    shared: Shared,
+
+    #[serde(skip)]
+    data_path: Option<PathBuf>,
+
+    #[serde(skip)]
+    data_error: Option<String>,
+
+    /// When the library file was last loaded or saved here.
+    #[serde(skip)]
+    data_modified: Option<SystemTime>,
+
+    #[serde(skip)]
+    budgets: Budgets,
+
+    /// What the Launch page shows of each game's playtime.
+    #[serde(skip)]
+    stats: HashMap<Uuid, GameStats>,
+
+    /// The tracker pass `stats` is from, `None` once the library or settings
+    /// changed.
+    #[serde(skip)]
+    stats_pass: Option<u64>,
+}
+
+/// Playtime of a game for the Launch page, worked out when the tracker
+/// records something rather than every frame.
+struct GameStats {
+    key: String,
+    total: u64,
+    this_week: u64,
+    last_played: Option<u64>,
+    per_day: Vec<(NaiveDate, u64)>,
+    /// The budget with the least left.
+    limit: Option<Limit>,
 }
 
 impl Default for GameLunch {
     fn default() -> Self {
         Self {
             page: Page::Home,
-            games: Vec::new(),
-            game: Game {
-                name: "".to_string(),
-                author: "".to_string(),
-                location: "".to_string().into(),
-            },
+            library: Library::default(),
+            game: Game::default(),
 
+            target_kind: "executable".to_string(),
             location: "".to_string(),
+            rom_input: "".to_string(),
+            rule_kind: "exe_name".to_string(),
+            rule_value: "".to_string(),
+            args_input: "".to_string(),
+            env_key: "".to_string(),
+            env_value: "".to_string(),
+            working_dir_input: "".to_string(),
+            prefix_input: "".to_string(),
 
             status: "".to_string(),
             launch_status: "".to_string(),
 
-            procs: Vec::new(),
+            confirm_launch: None,
+            confirm_delete_prefix: None,
+            confirm_discard: None,
+            confirm_empty_trash: false,
+            editing: None,
+            undo: None,
 
-            time: Arc::new(Mutex::new(HashMap::new())),
-
-            thread_spawned: false,
+            tracker: Tracker::default(),
 
             removed_values: Vec::new(),
+
+            settings: Settings::default(),
+
+            allow_input: "".to_string(),
+            tracking_status: "".to_string(),
+
+            trigger_input: "".to_string(),
+            class_name: "".to_string(),
+            class_days: [false; 7],
+            class_start: "08:00".to_string(),
+            class_end: "15:00".to_string(),
+            ics_path: "".to_string(),
+            schedule_status: "".to_string(),
+
+            wrapper_name: "".to_string(),
+            wrapper_command: "".to_string(),
+            wrapper_env: "".to_string(),
+            wrapper_status: "".to_string(),
+
+            runner_name: "".to_string(),
+            runner_kind: RunnerKind::default(),
+            runner_path: "".to_string(),
+            runner_status: "".to_string(),
+
+            data_dir_input: "".to_string(),
+            data_dir_status: "".to_string(),
+
+            unlocked: None,
+            pin_input: "".to_string(),
+            new_pin: "".to_string(),
+            confirm_pin: "".to_string(),
+            lock_status: "".to_string(),
+
+            shared: Shared::default(),
+
+            data_path: None,
+
+            data_error: None,
+
+            data_modified: None,
+
+            budgets: Budgets::default(),
+            stats: HashMap::new(),
+            stats_pass: None,
         }
     }
 }
 
 impl GameLunch {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        let mut app: Self = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+
+        app.data_path = data::default_path();
+
+        if let Some(path) = &app.data_path {
+            match Data::open(path, data::legacy) {
+                Ok(mut data) => {
+                    *app.shared.restore.lock().unwrap() = data.restore.take();
+
+                    let (library, history, settings) = data.into_parts();
+
+                    app.library = library;
+                    app.tracker = Tracker::new(history);
+                    app.settings = settings;
+                    app.data_modified = data::modified(path);
+                }
+                Err(err) => {
+                    // keep the broken file as is instead of overwriting it
+                    app.data_error = Some(format!("{}: {}", path.display(), err));
+                }
+            }
         }
 
-        // spawn gametime thread
+        app.shared.tracker = app.tracker.clone();
 
-        Default::default()
-    }
-}
+        app.page = match app.settings.startup_page {
+            StartupPage::Last => app.page,
+            StartupPage::Home => Page::Home,
+            StartupPage::Launch => Page::Launch,
+            StartupPage::AddGame => Page::AddGame,
+            StartupPage::ProcessTime => Page::ProcTime,
+            StartupPage::Settings => Page::Settings,
+        };
 
-impl eframe::App for GameLunch {
-    fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, self);
+        if app.data_error.is_none() {
+            *app.shared.data_path.lock().unwrap() = app.data_path.clone();
+        }
+
+        app.update_filter();
+
+        {
+            let tracker = app.tracker.clone();
+            let ctx = cc.egui_ctx.clone();
+
+            launcher::reap_in_background(app.shared.launcher.clone(), move |exited| {
+                log::info!(
+                    "{} ({}) exited with {:?}, signal {:?}",
+                    exited.game,
+                    exited.pid,
+                    exited.code,
+                    exited.signal
+                );
+
+                tracker
+                    .history()
+                    .record_exit(&exited.key, exited.pid, exited.code, exited.signal);
+
+                ctx.request_repaint();
+            });
+        }
+
+        {
+            let shared = app.shared.clone();
+            let ctx = cc.egui_ctx.clone();
+
+            app.tracker.on_trigger(move |fired| {
+                log::info!("Auto panic: {}", fired.join(", "));
+
+                if let Some(path) = triggers::log_path() {
+                    if let Err(err) = triggers::append_log(&path, now(), fired) {
+                        log::warn!("Failed to log triggers: {}", err);
+                    }
+                }
+
+                shared.panic(&ctx);
+
+                ctx.request_repaint();
+            });
+        }
+
+        {
+            let shared = app.shared.clone();
+            let ctx = cc.egui_ctx.clone();
+
+            app.tracker.on_budget(move |alerts| {
+                for alert in alerts {
+                    log::info!("Budget: {}", alert);
+
+                    let mut status = alert.to_string();
+
+                    if let Alert::Exhausted { id, .. } = alert {
+                        if shared.settings.lock().unwrap().budget_kill {
+                            status = format!("{}, {}", status, shared.kill_game(*id));
+                        }
+                    }
+
+                    *shared.status.lock().unwrap() = status;
+                }
+
+                ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                    egui::UserAttentionType::Informational,
+                ));
+                ctx.request_repaint();
+            });
+        }
+
+        let shared = app.shared.clone();
+        let ctx = cc.egui_ctx.clone();
+
+        let handler = move |command| {
+            let reply = match command {
+                Command::Panic => shared.panic(&ctx),
+                Command::Resume => shared.resume(),
+            };
+
+            ctx.request_repaint();
+
+            reply
+        };
+
+        if let Err(err) = control::listen(control::socket_path().as_deref(), handler) {
+            log::warn!("Not listening for panic commands: {}", err);
+        }
+
+        app
     }
 
-    fn auto_save_interval(&self) -> std::time::Duration {
-        std::time::Duration::from_secs(3)
+    fn data(&self) -> Data {
+        Data::new(
+            self.library.clone(),
+            &self.tracker.history(),
+            self.settings.clone(),
+            self.shared.restore.lock().unwrap().clone(),
+        )
     }
 
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // spawn thread on first run
-        if !self.thread_spawned {
-            // spawn thread
+    fn launch(&mut self, game: &Game) {
+        let allowed = self.budgets.allow(&self.tracker.history(), game.id, now());
+
+        let launched = allowed.and_then(|()| self.shared.launcher.lock().unwrap().launch(game));
+
+        self.launch_status = match launched {
+            Ok(Some(pid)) => {
+                self.tracker.track_launch(pid, game);
+
+                "Launched game".to_string()
+            }
+            // only its processes show it running
+            Ok(None) => format!("Opened {}, it can't be stopped from here", game.name),
+            Err(err) => {
+                log::warn!("{}", err);
 
-            let time = self.time.clone();
+                err.to_string()
+            }
+        };
+    }
 
-            std::thread::spawn(move || {
-                let mut system = System::new_all();
+    /// Create, open and delete buttons for the Wine prefix of `game`.
+    fn prefix_controls(&mut self, ui: &mut egui::Ui, game: &Game) {
+        let Some(prefix) = runner::prefix(game) else {
+            return;
+        };
 
-                loop {
-                    system.refresh_all();
+        egui::CollapsingHeader::new("Wine prefix")
+            .id_source(("prefix", game.id))
+            .show(ui, |ui| {
+                ui.label(prefix.display().to_string());
 
-                    let mut hashmap = time.lock().unwrap();
+                ui.horizontal(|ui| {
+                    if ui.button("Create").clicked() {
+                        let runner = self
+                            .shared
+                            .launcher
+                            .lock()
+                            .unwrap()
+                            .runner(game)
+                            .map(|runner| runner.cloned());
+
+                        match runner {
+                            Ok(Some(runner)) => {
+                                let status = self.shared.status.clone();
+                                let ctx = ui.ctx().clone();
+                                let name = game.name.clone();
+                                let prefix = prefix.clone();
+
+                                *status.lock().unwrap() = format!("Creating prefix for {}", name);
+
+                                // wineboot takes a while on first run
+                                std::thread::spawn(move || {
+                                    *status.lock().unwrap() = match runner.create_prefix(&prefix) {
+                                        Ok(()) => format!("Created prefix for {}", name),
+                                        Err(err) => err.to_string(),
+                                    };
+
+                                    ctx.request_repaint();
+                                });
+                            }
+                            Ok(None) => {}
+                            Err(err) => *self.shared.status.lock().unwrap() = err.to_string(),
+                        }
+                    }
 
-                    // calculates which processes are running
+                    if ui.button("Open folder").clicked() {
+                        if let Err(err) = self.shared.launcher.lock().unwrap().open(&prefix) {
+                            *self.shared.status.lock().unwrap() = err.to_string();
+                        }
+                    }
 
-                    let mut names = vec![];
+                    // deleting the prefix deletes the game's saves with it
+                    if ui
+                        .add_enabled(self.unlocked(), egui::Button::new("Delete"))
+                        .clicked()
+                    {
+                        self.confirm_delete_prefix = Some(game.id);
+                    }
+                });
 
-                    system.processes().iter().for_each(|(_pid, process)| {
-                        let name = process.name().to_string_lossy().to_lowercase();
+                if self.confirm_delete_prefix == Some(game.id) {
+                    ui.horizontal(|ui| {
+                        ui.label("Delete the prefix with everything installed in it?");
 
-                        if !names.contains(&name) {
-                            let val = hashmap.get(&name).unwrap_or(&0) + 5;
+                        if ui
+                            .add_enabled(self.unlocked(), egui::Button::new("Delete"))
+                            .clicked()
+                        {
+                            *self.shared.status.lock().unwrap() =
+                                match runner::delete_prefix(&prefix) {
+                                    Ok(()) => format!("Deleted prefix of {}", game.name),
+                                    Err(err) => err.to_string(),
+                                };
 
-                            hashmap.insert(name.clone(), val);
+                            self.confirm_delete_prefix = None;
+                        }
 
-                            names.push(name);
+                        if ui.button("Cancel").clicked() {
+                            self.confirm_delete_prefix = None;
                         }
                     });
+                }
+            });
+    }
 
-                    std::mem::drop(hashmap);
+    /// Relaunches the games panic killed. Those that fail to start stay
+    /// offered.
+    fn restore(&mut self) {
+        let Some(mut snapshot) = self.shared.restore.lock().unwrap().take() else {
+            return;
+        };
+
+        let mut restored = 0;
+        let mut errors = Vec::new();
+
+        let budgets = Budgets::new(&self.settings, &self.library);
+        let history = self.tracker.history().clone();
+
+        for result in snapshot.relaunch(
+            &mut self.shared.launcher.lock().unwrap(),
+            &self.library,
+            &budgets,
+            &history,
+            now(),
+        ) {
+            match result {
+                Ok((game, pid)) => {
+                    if let Some(pid) = pid {
+                        self.tracker.track_launch(pid, &game);
+                    }
 
-                    std::thread::sleep(std::time::Duration::from_secs(5));
+                    restored += 1;
                 }
-            });
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+
+        *self.shared.status.lock().unwrap() = match errors.is_empty() {
+            true => format!("Restored {} games", restored),
+            false => errors.join(", "),
+        };
+
+        if !snapshot.games.is_empty() {
+            *self.shared.restore.lock().unwrap() = Some(snapshot);
+        }
+    }
+
+    /// Opens the Add Game page filled in with `game`, to save it back over
+    /// itself.
+    fn edit(&mut self, game: &Game) {
+        let (location, rom) = game.location.parts();
+
+        self.game = game.clone();
+        self.target_kind = game.location.kind().to_string();
+        self.location = location;
+        self.rom_input = rom;
+        self.args_input = join_args(&game.args);
+        self.working_dir_input = game
+            .working_dir
+            .as_ref()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        self.prefix_input = game
+            .wine
+            .as_ref()
+            .and_then(|wine| wine.prefix.as_ref())
+            .map(|prefix| prefix.display().to_string())
+            .unwrap_or_default();
+
+        self.status = "".to_string();
+        self.editing = Some(game.id);
+        self.page = Page::AddGame;
+    }
+
+    /// Replaces the game with id `id` with `game`, taking its playtime and
+    /// running copies along.
+    fn save_edit(&mut self, id: Uuid, game: Game) -> crate::core::Result<()> {
+        let old = self.library.edit(id, game)?;
+        let Some(game) = self.library.find(id) else {
+            return Ok(());
+        };
+
+        self.tracker.history().rename(&old.key(), &game.key());
+        self.shared.launcher.lock().unwrap().update(game);
+
+        Ok(())
+    }
+
+    /// Moves the game with id `id` to the trash and offers to undo it.
+    fn remove(&mut self, id: Uuid) {
+        let removed = self.library.remove(id, &mut self.tracker.history(), now());
+
+        self.launch_status = match removed {
+            Ok(trashed) => {
+                self.undo = Some((id, Instant::now()));
+
+                format!("Moved {} to the trash", trashed.game.name)
+            }
+            Err(err) => err.to_string(),
+        };
+
+        self.update_filter();
+    }
+
+    /// Puts the game with id `id` back from the trash.
+    fn restore_game(&mut self, id: Uuid) {
+        let restored = self.library.restore(id, &mut self.tracker.history());
+
+        self.launch_status = match restored {
+            Ok(game) => format!("Restored {}", game.name),
+            Err(err) => err.to_string(),
+        };
+
+        self.undo = None;
+
+        self.update_filter();
+    }
+
+    /// Empties the Add Game page and leaves edit mode.
+    fn clear_form(&mut self) {
+        self.game = Game::default();
+        self.target_kind = "executable".to_string();
+        self.location = "".to_string();
+        self.rom_input = "".to_string();
+        self.rule_kind = "exe_name".to_string();
+        self.rule_value = "".to_string();
+        self.args_input = "".to_string();
+        self.env_key = "".to_string();
+        self.env_value = "".to_string();
+        self.working_dir_input = "".to_string();
+        self.prefix_input = "".to_string();
+
+        self.status = "".to_string();
+        self.editing = None;
+    }
+
+    /// Whether locked actions are allowed: there is no lock, or the PIN was
+    /// entered recently.
+    fn unlocked(&self) -> bool {
+        !self.settings.lock.is_set()
+            || self
+                .unlocked
+                .is_some_and(|time| time.elapsed() < UNLOCK_TIME)
+    }
+
+    /// Checks the PIN typed in the top bar.
+    fn unlock(&mut self) {
+        let secret = std::mem::take(&mut self.pin_input);
+
+        if secret.is_empty() {
+            return;
+        }
+
+        // a PIN changed from the command line applies
+        self.reload_changed();
+
+        let result = self
+            .settings
+            .lock
+            .unlock(&secret, "unlock the launcher", now());
+
+        self.update_filter();
+
+        match result {
+            Ok(()) => {
+                self.unlocked = Some(Instant::now());
+                self.lock_status = "".to_string();
+            }
+            Err(err) => self.lock_status = err.to_string(),
+        }
+    }
+
+    /// Takes over what another program, like the command line, saved to the
+    /// library file since the window last did, so saving doesn't undo it.
+    fn reload(&mut self, path: &Path) {
+        let mut data = match Data::load(path) {
+            Ok(data) => data,
+            // deleted, saving writes it again
+            Err(crate::core::Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                return
+            }
+            Err(err) => {
+                // keep the broken file as is instead of overwriting it
+                self.data_error = Some(format!("{}: {}", path.display(), err));
+
+                return;
+            }
+        };
+
+        log::info!(
+            "Reloading {}, it was changed outside the launcher",
+            path.display()
+        );
+
+        *self.shared.restore.lock().unwrap() = data.restore.take();
+
+        let (library, history, settings) = data.into_parts();
+
+        self.library = library;
+        self.tracker.history().reload(history);
+        self.settings = settings;
+
+        self.update_filter();
+    }
+
+    /// Picks up outside changes to the library file, if there are any.
+    fn reload_changed(&mut self) {
+        let Some(path) = self.data_path.clone() else {
+            return;
+        };
+
+        if self.data_error.is_none() && data::modified(&path) != self.data_modified {
+            self.reload(&path);
+        }
+    }
+
+    /// Writes the library file, after taking over outside changes to it.
+    fn save_data(&mut self) {
+        self.reload_changed();
+
+        let Some(path) = self.data_path.clone() else {
+            return;
+        };
+
+        if self.data_error.is_none() {
+            let keys: Vec<String> = self.library.games().iter().map(Game::key).collect();
+
+            self.tracker.history().compact(
+                |process| keys.iter().any(|key| key == process),
+                now().saturating_sub(COMPACT_DAYS * 24 * 60 * 60),
+            );
+
+            match self.data().save(&path) {
+                Ok(()) => self.data_modified = data::modified(&path),
+                Err(err) => log::warn!("Failed to save {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    /// Tells the tracker and the panic routine about changes to the library
+    /// or settings.
+    fn update_filter(&mut self) {
+        self.budgets = Budgets::new(&self.settings, &self.library);
+        self.stats_pass = None;
+
+        self.tracker
+            .set_interval(Duration::from_secs(self.settings.scan_interval_secs.max(1)));
+        self.tracker
+            .set_filter(Filter::new(&self.settings, &self.library));
+        self.tracker.set_triggers(Triggers::new(&self.settings));
+        self.tracker.set_budgets(self.budgets.clone());
+        self.shared
+            .launcher
+            .lock()
+            .unwrap()
+            .configure(&self.settings);
+
+        *self.shared.library.lock().unwrap() = self.library.clone();
+        *self.shared.settings.lock().unwrap() = self.settings.clone();
+    }
+
+    /// Works out `stats` again if the tracker recorded something since.
+    fn refresh_stats(&mut self) {
+        let pass = self.tracker.passes();
+
+        if self.stats_pass == Some(pass) {
+            return;
+        }
+
+        let history = self.tracker.history();
+        let now = now();
+
+        let stats = self
+            .library
+            .games()
+            .iter()
+            .map(|game| {
+                // keys are read from disk, keep them while the library is
+                // the same
+                let key = match (self.stats_pass, self.stats.get(&game.id)) {
+                    (Some(_), Some(stats)) => stats.key.clone(),
+                    _ => game.key(),
+                };
+
+                let stats = GameStats {
+                    total: history.total(&key),
+                    this_week: history.this_week(&key),
+                    last_played: history.last_played(&key),
+                    per_day: history.per_day(&key, 7),
+                    limit: self
+                        .budgets
+                        .limits(&history, game.id, now)
+                        .into_iter()
+                        .next(),
+                    key,
+                };
+
+                (game.id, stats)
+            })
+            .collect();
+
+        drop(history);
+
+        self.stats = stats;
+        self.stats_pass = Some(pass);
+    }
+}
+
+impl eframe::App for GameLunch {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+
+        self.save_data();
+    }
+
+    fn auto_save_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(3)
+    }
 
-            self.thread_spawned = true;
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // spawn thread on first run
+        self.tracker.start();
+
+        let dark = match self.settings.theme {
+            Theme::System => frame.info().system_theme != Some(eframe::Theme::Light),
+            Theme::Light => false,
+            Theme::Dark => true,
+        };
+
+        if ctx.style().visuals.dark_mode != dark {
+            ctx.set_visuals(if dark {
+                egui::Visuals::dark()
+            } else {
+                egui::Visuals::light()
+            });
         }
+
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.selectable_value(&mut self.page, Page::Home, "Home");
@@ -126,70 +842,115 @@ impl eframe::App for GameLunch {
                 ui.selectable_value(&mut self.page, Page::ProcTime, "Process Time");
                 ui.selectable_value(&mut self.page, Page::Settings, "Settings");
 
+                if self.settings.lock.is_set() {
+                    ui.separator();
+
+                    match self.unlocked {
+                        Some(time) if time.elapsed() < UNLOCK_TIME => {
+                            if ui
+                                .button("Lock")
+                                .on_hover_text("Ask for the PIN again")
+                                .clicked()
+                            {
+                                self.unlocked = None;
+                            }
+
+                            // lock again when the time is up
+                            ui.ctx().request_repaint_after(UNLOCK_TIME - time.elapsed());
+                        }
+                        _ => {
+                            let input = ui.add(
+                                egui::TextEdit::singleline(&mut self.pin_input)
+                                    .password(true)
+                                    .hint_text("PIN")
+                                    .desired_width(100.0),
+                            );
+
+                            let entered = input.lost_focus()
+                                && ui.input(|input| input.key_pressed(egui::Key::Enter));
+
+                            if ui.button("Unlock").clicked() || entered {
+                                self.unlock();
+                            }
+
+                            ui.label(&self.lock_status);
+                        }
+                    }
+                }
+
                 if ui.button("Close Launcher").clicked() {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                 }
             });
         });
 
+        // leaving the page drops the edit, so Add Game doesn't save over the
+        // game later
+        if self.editing.is_some() && self.page != Page::AddGame {
+            self.clear_form();
+        }
+
         egui::TopBottomPanel::bottom("bottom").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 if ui.button("PANIC").clicked() {
-                    // kill all subprocesses
+                    self.shared.panic(ctx);
+                }
 
-                    for proc in &mut self.procs {
-                        println!("Killing {:?}", proc);
-                        let _ = proc.kill(); // this unwrap doesnt matter
-                    }
+                if self.settings.panic_mode == PanicMode::Suspend && ui.button("Resume").clicked() {
+                    self.shared.resume();
+                }
 
-                    #[cfg(unix)]
-                    {
-                        // kill all processes on linux only
+                let restore = self.shared.restore.lock().unwrap().clone();
 
-                        for game in &self.games {
-                            let loc = game
-                                .location
-                                .to_string_lossy()
-                                .split('/')
-                                .last()
-                                .unwrap_or("")
-                                .to_string();
+                if let Some(snapshot) = restore {
+                    ui.label(format!(
+                        "{} games were running at panic ({})",
+                        snapshot.games.len(),
+                        format_date(snapshot.time)
+                    ));
 
-                            std::process::Command::new("sh")
-                                .arg("-c")
-                                .arg(format!("kill $(pidof {})", loc))
-                                .status()
-                                .unwrap_or_default();
-                        }
+                    // both drawn every frame, so Dismiss doesn't flicker away
+                    // while Restore is pressed
+                    let restore = ui.button("Restore").clicked();
+                    let dismiss = ui.button("Dismiss").clicked();
+
+                    if restore {
+                        self.restore();
+                    } else if dismiss {
+                        *self.shared.restore.lock().unwrap() = None;
                     }
+                }
 
-                    #[cfg(not(unix))]
-                    {
-                        // kill all processes on windows
+                if let Some((id, removed)) = self.undo {
+                    let name = self
+                        .library
+                        .trash()
+                        .iter()
+                        .find(|trashed| trashed.game.id == id)
+                        .map(|trashed| trashed.game.name.clone());
 
-                        for game in &self.games {
-                            for game in &self.games {
-                                let loc = game
-                                    .location
-                                    .to_string_lossy()
-                                    .split('\\')
-                                    .last()
-                                    .unwrap_or("")
-                                    .to_string();
+                    match name {
+                        Some(name) if removed.elapsed() < UNDO_TIME => {
+                            ui.label(format!("Removed {}", name));
 
-                                std::process::Command::new("cmd")
-                                    .arg("/C")
-                                    .arg(format!("taskkill $(pidof {})", loc))
-                                    .status()
-                                    .unwrap_or_default();
+                            if ui.button("Undo").clicked() {
+                                self.restore_game(id);
                             }
+
+                            ui.ctx()
+                                .request_repaint_after(UNDO_TIME - removed.elapsed());
                         }
+                        _ => self.undo = None,
                     }
-
-                    std::process::exit(0);
                 }
 
+                ui.label(self.shared.status.lock().unwrap().as_str());
+
                 ui.label("GameLunch v0.1.0 by Aityz");
+
+                if let Some(err) = &self.data_error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
             });
         });
 
@@ -219,25 +980,96 @@ impl eframe::App for GameLunch {
                     }
                 });
             });
-        }
 
-        egui::CentralPanel::default().show(ctx, |ui| match self.page {
-            Page::Home => {
-                ui.vertical_centered(|ui| {
-                    ui.heading("Welcome to GameLunch");
-                    ui.label("GameLunch is a game launcher for games that may be in scattered folders, and is made to organize your game library. It is like steam, but without the marketplace");
+            egui::SidePanel::right("right").show(ctx, |ui| {
+                let settings = self.settings.clone();
 
-                    ui.heading("Features");
+                if !self.unlocked() {
+                    ui.disable();
+                }
 
-                    ui.label("- Panic button, isntantly kill all games and the launcher");
-                    ui.label("- Fast, as it is written in Rust");
-                    ui.label("- Track time on all games");
-                    ui.label("- Supports all non-DRM games (you must crack DRM games to use them here)");
-                    ui.label("- Works on Linux, macOS and Windows");
+                ui.heading("Tracking");
 
-                    if ui.button("Get Started").clicked() {
-                        self.page = Page::AddGame;
-                    }
+                for scope in TrackingScope::ALL {
+                    ui.radio_value(&mut self.settings.tracking_scope, scope, scope.label());
+                }
+
+                ui.checkbox(&mut self.settings.use_denylist, "Skip system processes");
+
+                ui.separator();
+
+                ui.label("Allowlist");
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.allow_input);
+
+                    if ui.button("Add").clicked() && !self.allow_input.trim().is_empty() {
+                        self.settings
+                            .allowlist
+                            .push(self.allow_input.trim().to_string());
+                        self.settings.allowlist.dedup();
+
+                        self.allow_input = "".to_string();
+                    }
+                });
+
+                let mut i = 0;
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for val in self.settings.allowlist.clone() {
+                        ui.horizontal(|ui| {
+                            ui.label(val);
+
+                            if ui.button("Remove").clicked() {
+                                self.settings.allowlist.remove(i);
+                            }
+                        });
+
+                        i += 1;
+                    }
+                });
+
+                ui.separator();
+
+                if ui
+                    .button("Prune untracked")
+                    .on_hover_text("Delete recorded time of processes outside the tracking scope")
+                    .clicked()
+                {
+                    let filter = Filter::new(&self.settings, &self.library);
+                    let removed = self
+                        .tracker
+                        .history()
+                        .prune(|process| filter.tracks(process));
+
+                    self.tracking_status = format!("Removed {} processes", removed);
+                }
+
+                ui.label(&self.tracking_status);
+
+                if self.settings != settings {
+                    self.update_filter();
+                }
+            });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| match self.page {
+            Page::Home => {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Welcome to GameLunch");
+                    ui.label("GameLunch is a game launcher for games that may be in scattered folders, and is made to organize your game library. It is like steam, but without the marketplace");
+
+                    ui.heading("Features");
+
+                    ui.label("- Panic button, isntantly kill all games and the launcher");
+                    ui.label("- Fast, as it is written in Rust");
+                    ui.label("- Track time on all games");
+                    ui.label("- Supports all non-DRM games (you must crack DRM games to use them here)");
+                    ui.label("- Works on Linux, macOS and Windows");
+
+                    if ui.button("Get Started").clicked() {
+                        self.page = Page::AddGame;
+                    }
                 });
             }
 
@@ -246,53 +1078,201 @@ impl eframe::App for GameLunch {
                     ui.heading("Launch Game");
                 });
 
-                let mut i = 0;
+                let mut launch = None;
+                let mut edit = None;
+                let mut remove = None;
 
-                // get game time data
+                let unlocked = self.unlocked();
 
-                let data = self.time.lock().unwrap();
+                self.refresh_stats();
 
-                for game in self.games.clone() { // data is cloned to save borrow checker
-                    ui.horizontal(|ui| {
+                let games = self.library.games().to_vec();
 
-                        // get the data
+                for game in &games { // data is cloned to save borrow checker
+                    let Some(stats) = self.stats.get(&game.id) else {
+                        continue;
+                    };
 
-                        let mut sep = "/";
+                    let running = self
+                        .shared
+                        .launcher
+                        .lock()
+                        .unwrap()
+                        .running(game.id)
+                        .map(|proc| (proc.started.elapsed(), proc.stopping));
 
-                        #[cfg(not(unix))]
-                        {
-                            sep = "\\";
+                    let per_day = stats.per_day.clone();
+
+                    ui.horizontal(|ui| {
+                        let last_played = match stats.last_played {
+                            Some(time) => format!("last played {}", format_date(time)),
+                            None => "never played".to_string(),
+                        };
+
+                        ui.label(format!("{} by {}, {} ({} this week), {}", game.name, game.author, format_time(&stats.total), format_time(&stats.this_week), last_played));
+
+                        if let Some(limit) = &stats.limit {
+                            match limit.left() {
+                                0 => ui.colored_label(ui.visuals().warn_fg_color, "No playtime left"),
+                                left => ui.label(format!("{} left", format_time(&left))),
+                            }
+                            .on_hover_text(limit.to_string());
                         }
 
-                        let time = game.location.to_string_lossy().split(sep).last().unwrap_or_default().to_lowercase();
+                        if let Some((elapsed, stopping)) = running {
+                            ui.label(format!("Running for {}", format_duration(elapsed.as_secs())));
 
-                        ui.label(format!("{} by {}, {}", game.name, game.author, format_time(data.get(&time).unwrap_or(&0))));
-                        if ui.button("Launch").clicked() {
-                            let proc = std::process::Command::new(&game.location).spawn();
+                            if ui.button(if stopping { "Kill" } else { "Stop" }).clicked() {
+                                self.shared.launcher.lock().unwrap().stop(game.id);
+                            }
+                        }
 
-                            if let Ok(proc) = proc {
-                                self.procs.push(proc);
+                        if ui.button("Dry run").on_hover_text("Show the command without running it").clicked() {
+                            self.launch_status = match self.shared.launcher.lock().unwrap().command_line(game, &game.args) {
+                                Ok(line) => line.to_string(),
+                                Err(err) => err.to_string(),
+                            };
+                        }
 
-                                self.launch_status = "Launched game".to_string();
+                        if ui.button("Launch").clicked() {
+                            if running.is_some() {
+                                self.confirm_launch = Some(game.id);
                             } else {
-                                self.launch_status = "Failed to launch game".to_string();
+                                launch = Some(game.clone());
                             }
                         }
-                        if ui .button("Remove").clicked() {
-                            let _ = self.games.remove(i);
+                        if ui.add_enabled(unlocked, egui::Button::new("Edit")).clicked() {
+                            edit = Some(game.clone());
                         }
 
-                        i += 1;
+                        if ui.add_enabled(unlocked, egui::Button::new("Remove")).on_hover_text("Move to the trash").clicked() {
+                            remove = Some(game.id);
+                        }
+                    });
+
+                    if self.confirm_launch == Some(game.id) {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} is already running. Start another copy?", game.name));
+
+                            if ui.button("Launch anyway").clicked() {
+                                launch = Some(game.clone());
+
+                                self.confirm_launch = None;
+                            }
+
+                            if ui.button("Cancel").clicked() {
+                                self.confirm_launch = None;
+                            }
+                        });
+                    }
+
+                    egui::CollapsingHeader::new("Last 7 days").id_source(("history", game.id)).show(ui, |ui| {
+                        for (day, time) in &per_day {
+                            ui.label(format!("{}: {}", day.format("%a %d %b"), format_time(time)));
+                        }
                     });
+
+                    if game.wine.is_some() {
+                        self.prefix_controls(ui, game);
+                    }
+
+                    if running.is_some() {
+                        // keep the running time ticking
+                        ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+                    }
+                }
+
+                if let Some(game) = launch {
+                    self.launch(&game);
+                }
+
+                if let Some(game) = edit {
+                    self.edit(&game);
+                }
+
+                if let Some(id) = remove {
+                    self.remove(id);
                 }
 
                 ui.separator();
 
                 ui.label(&self.launch_status);
+
+                let trash = self.library.trash().to_vec();
+
+                if !trash.is_empty() {
+                    egui::CollapsingHeader::new(format!("Trash ({})", trash.len())).show(ui, |ui| {
+                        ui.label(format!("Removed games and their playtime are kept for {} days", TRASH_DAYS));
+
+                        for trashed in trash.iter().rev() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "{} by {}, {}, removed {}",
+                                    trashed.game.name,
+                                    trashed.game.author,
+                                    format_time(&trashed.seconds()),
+                                    format_date(trashed.removed)
+                                ));
+
+                                if ui.add_enabled(unlocked, egui::Button::new("Restore")).clicked() {
+                                    self.restore_game(trashed.game.id);
+                                }
+
+                                if ui.add_enabled(unlocked, egui::Button::new("Delete forever")).clicked() {
+                                    self.confirm_discard = Some(trashed.game.id);
+                                }
+                            });
+
+                            if self.confirm_discard == Some(trashed.game.id) {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("Delete {} and its playtime for good?", trashed.game.name));
+
+                                    if ui.add_enabled(unlocked, egui::Button::new("Delete")).clicked() {
+                                        self.launch_status = match self.library.discard(trashed.game.id) {
+                                            Ok(trashed) => format!("Deleted {}", trashed.game.name),
+                                            Err(err) => err.to_string(),
+                                        };
+
+                                        self.confirm_discard = None;
+                                    }
+
+                                    if ui.button("Cancel").clicked() {
+                                        self.confirm_discard = None;
+                                    }
+                                });
+                            }
+                        }
+
+                        if ui.add_enabled(unlocked, egui::Button::new("Empty trash")).clicked() {
+                            self.confirm_empty_trash = true;
+                        }
+
+                        if self.confirm_empty_trash {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("Delete all {} games in the trash and their playtime?", trash.len()));
+
+                                if ui.add_enabled(unlocked, egui::Button::new("Empty")).clicked() {
+                                    let deleted = self.library.empty_trash(None);
+
+                                    self.launch_status = format!("Deleted {} games", deleted);
+                                    self.undo = None;
+                                    self.confirm_empty_trash = false;
+                                }
+
+                                if ui.button("Cancel").clicked() {
+                                    self.confirm_empty_trash = false;
+                                }
+                            });
+                        }
+                    });
+                }
             }
 
             Page::AddGame => {
-                ui.heading("Add Game");
+                match self.editing.and_then(|id| self.library.find(id)) {
+                    Some(game) => ui.heading(format!("Edit {}", game.name)),
+                    None => ui.heading("Add Game"),
+                };
 
                 ui.horizontal(|ui| {
                     ui.label("Name: ");
@@ -305,33 +1285,270 @@ impl eframe::App for GameLunch {
                 });
 
                 ui.horizontal(|ui| {
-                    ui.label("Location: ");
-                    ui.text_edit_singleline(&mut self.location);
+                    ui.label("Launch: ");
+
+                    egui::ComboBox::from_id_source("target_kind")
+                        .selected_text(self.target_kind.as_str())
+                        .show_ui(ui, |ui| {
+                            for kind in LaunchTarget::KINDS {
+                                ui.selectable_value(&mut self.target_kind, kind.to_string(), kind);
+                            }
+                        });
+
+                    let hint = match self.target_kind.as_str() {
+                        "shell" => "./start.sh --fullscreen",
+                        "url" => "https://",
+                        "flatpak" => "org.example.Game",
+                        "steam" => "app id",
+                        "emulator" => "emulator executable",
+                        _ => "game executable",
+                    };
+
+                    ui.add(egui::TextEdit::singleline(&mut self.location).hint_text(hint));
+
+                    if self.target_kind == "emulator" {
+                        ui.add(egui::TextEdit::singleline(&mut self.rom_input).hint_text("ROM"));
+                    }
                 });
 
-                if ui.button("Add Game").clicked() {
-                    // do some calculating
-
-                    let path = std::path::PathBuf::from(&self.location);
-
-                    if !path.exists() {
-                        self.status = "Game does not exist".to_string();
-                    } else if self.game.name.is_empty() {
-                        self.status = "Game requires a name".to_string();
-                    } else if self.game.author.is_empty() {
-                        self.status = "Game requires an author".to_string();
-                    } else {
-                        self.games.push(Game {
-                            name: self.game.name.clone(),
-                            author: self.game.author.clone(),
-                            location: path
+                let program = std::path::Path::new(self.location.trim());
+
+                if matches!(self.target_kind.as_str(), "executable" | "emulator") && program.is_file() {
+                    match Plan::new(program, self.game.wine.is_some()) {
+                        Ok(plan) => ui.label(format!("Detected {}", plan)),
+                        Err(err) => ui.colored_label(ui.visuals().warn_fg_color, err.to_string()),
+                    };
+                }
+
+                ui.label("Match rules (for games started by a launcher or wrapper):");
+
+                for (i, rule) in self.game.rules.clone().into_iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(rule.to_string());
+
+                        if ui.button("Remove").clicked() {
+                            self.game.rules.remove(i);
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("rule_kind")
+                        .selected_text(self.rule_kind.as_str())
+                        .show_ui(ui, |ui| {
+                            for kind in MatchRule::KINDS {
+                                ui.selectable_value(&mut self.rule_kind, kind.to_string(), kind);
+                            }
                         });
 
-                        self.game.author = "".to_string();
-                        self.game.name = "".to_string();
-                        self.location = "".to_string();
+                    if self.rule_kind != "descendants" {
+                        ui.text_edit_singleline(&mut self.rule_value);
+                    }
+
+                    if ui.button("Add Rule").clicked() {
+                        match MatchRule::new(&self.rule_kind, &self.rule_value) {
+                            Ok(rule) => {
+                                self.game.rules.push(rule);
 
-                        self.status = "".to_string();
+                                self.rule_value = "".to_string();
+                                self.status = "".to_string();
+                            }
+                            Err(err) => self.status = err.to_string(),
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Arguments: ");
+                    ui.text_edit_singleline(&mut self.args_input);
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Working directory: ");
+                    ui.add(egui::TextEdit::singleline(&mut self.working_dir_input).hint_text("folder of the executable"));
+                });
+
+                ui.label("Environment:");
+
+                for (key, value) in self.game.env.clone() {
+                    ui.horizontal(|ui| {
+                        match value {
+                            Some(value) => ui.label(format!("{}={}", key, value)),
+                            None => ui.label(format!("unset {}", key)),
+                        };
+
+                        if ui.button("Remove").clicked() {
+                            self.game.env.remove(&key);
+                        }
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.env_key).hint_text("NAME").desired_width(120.0));
+                    ui.label("=");
+                    ui.add(egui::TextEdit::singleline(&mut self.env_value).desired_width(160.0));
+
+                    if ui.button("Set").clicked() && !self.env_key.trim().is_empty() {
+                        self.game.env.insert(self.env_key.trim().to_string(), Some(self.env_value.clone()));
+
+                        self.env_key = "".to_string();
+                        self.env_value = "".to_string();
+                    }
+
+                    if ui.button("Unset").clicked() && !self.env_key.trim().is_empty() {
+                        self.game.env.insert(self.env_key.trim().to_string(), None);
+
+                        self.env_key = "".to_string();
+                        self.env_value = "".to_string();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Runner: ");
+
+                    let selected = match &self.game.wine {
+                        Some(wine) => wine.runner.clone(),
+                        None => "Native".to_string(),
+                    };
+
+                    egui::ComboBox::from_id_source("runner")
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            if ui.selectable_label(self.game.wine.is_none(), "Native").clicked() {
+                                self.game.wine = None;
+                            }
+
+                            for runner in &self.settings.runners {
+                                let checked = self.game.wine.as_ref().is_some_and(|wine| wine.runner == runner.name);
+
+                                if ui.selectable_label(checked, &runner.name).clicked() {
+                                    let wine = self.game.wine.get_or_insert_with(WineConfig::default);
+
+                                    wine.runner = runner.name.clone();
+                                }
+                            }
+                        });
+
+                    if self.settings.runners.is_empty() {
+                        ui.label("(add Wine or Proton on the Settings page)");
+                    }
+                });
+
+                if let Some(wine) = &mut self.game.wine {
+                    ui.horizontal(|ui| {
+                        ui.label("Prefix: ");
+                        ui.add(egui::TextEdit::singleline(&mut self.prefix_input).hint_text("own prefix in the data folder"));
+                    });
+
+                    ui.horizontal(|ui| {
+                        for (label, key, value) in runner::TOGGLES {
+                            let mut checked = wine.env.get(*key).is_some_and(|set| set == value);
+
+                            if ui.checkbox(&mut checked, *label).changed() {
+                                match checked {
+                                    true => wine.env.insert(key.to_string(), value.to_string()),
+                                    false => wine.env.remove(*key),
+                                };
+                            }
+                        }
+                    });
+                }
+
+                if !self.settings.wrappers.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label("Wrappers: ");
+
+                        for wrapper in &self.settings.wrappers {
+                            let mut checked = wrapper.global || self.game.wrappers.contains(&wrapper.name);
+
+                            let response = ui.add_enabled(!wrapper.global, egui::Checkbox::new(&mut checked, &wrapper.name));
+
+                            if response.changed() {
+                                // stacked in the order they are ticked
+                                match checked {
+                                    true => self.game.wrappers.push(wrapper.name.clone()),
+                                    false => self.game.wrappers.retain(|name| name != &wrapper.name),
+                                }
+                            }
+                        }
+                    });
+
+                    if !self.game.wrappers.is_empty() {
+                        ui.label(format!("Run through {}", self.game.wrappers.join(" > ")));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Playtime budget: ");
+
+                    budget_controls(ui, &mut self.game.budget);
+                });
+
+                let unlocked = self.unlocked();
+
+                let (save, cancel) = ui
+                    .horizontal(|ui| match self.editing {
+                        Some(_) => (
+                            ui.add_enabled(unlocked, egui::Button::new("Save")).clicked(),
+                            ui.button("Cancel").clicked(),
+                        ),
+                        None => (ui.add_enabled(unlocked, egui::Button::new("Add Game")).clicked(), false),
+                    })
+                    .inner;
+
+                if cancel {
+                    self.clear_form();
+
+                    self.page = Page::Launch;
+                }
+
+                if save {
+                    let location = LaunchTarget::new(&self.target_kind, &self.location, &self.rom_input);
+
+                    let game = location.map(|location| Game {
+                        id: self.game.id,
+                        name: self.game.name.clone(),
+                        author: self.game.author.clone(),
+                        location,
+                        rules: self.game.rules.clone(),
+                        args: split_args(&self.args_input),
+                        env: self.game.env.clone(),
+                        working_dir: match self.working_dir_input.trim() {
+                            "" => None,
+                            dir => Some(dir.into()),
+                        },
+                        wrappers: self.game.wrappers.clone(),
+                        budget: self.game.budget,
+                        wine: self.game.wine.clone().map(|mut wine| {
+                            wine.prefix = match self.prefix_input.trim() {
+                                "" => None,
+                                prefix => Some(prefix.into()),
+                            };
+
+                            wine
+                        }),
+                    });
+
+                    let saved = match self.editing {
+                        // a second entry for a game could get around its budget
+                        _ if !self.unlocked() => {
+                            Err(crate::core::Error::Lock("unlock the launcher to save changes".to_string()))
+                        }
+                        Some(id) => game.and_then(|game| self.save_edit(id, game)),
+                        None => game.and_then(|game| self.library.add(game)),
+                    };
+
+                    match saved {
+                        Ok(()) => {
+                            self.update_filter();
+
+                            if self.editing.is_some() {
+                                self.page = Page::Launch;
+                            }
+
+                            self.clear_form();
+                        }
+                        Err(err) => self.status = err.to_string(),
                     }
                 }
 
@@ -343,7 +1560,7 @@ impl eframe::App for GameLunch {
                     ui.heading("Process Time");
                 });
 
-                let data = self.time.lock().unwrap();
+                let data = self.tracker.history().totals();
 
                 if ui.button("Hide All").clicked() {
                     for (key, _value) in data.iter() {
@@ -370,18 +1587,582 @@ impl eframe::App for GameLunch {
                 });
             }
 
-            _ => {}
+            Page::Settings if !self.unlocked() => {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Settings");
+
+                    ui.label("Settings are locked, enter the PIN at the top to change them");
+                });
+            }
+
+            Page::Settings => {
+                let settings = self.settings.clone();
+
+                ui.vertical_centered(|ui| {
+                    ui.heading("Settings");
+                });
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.label("General");
+
+                    egui::ComboBox::from_label("Open on start")
+                        .selected_text(self.settings.startup_page.label())
+                        .show_ui(ui, |ui| {
+                            for page in StartupPage::ALL {
+                                ui.selectable_value(&mut self.settings.startup_page, page, page.label());
+                            }
+                        });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Theme");
+
+                        for theme in Theme::ALL {
+                            ui.radio_value(&mut self.settings.theme, theme, theme.label());
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Data directory");
+
+                        match data::data_dir() {
+                            Some(dir) => ui.monospace(dir.display().to_string()),
+                            None => ui.label("unavailable"),
+                        };
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.data_dir_input);
+
+                        // moving a library that failed to load would replace it
+                        let enabled = self.data_error.is_none();
+
+                        let dir = if ui.add_enabled(enabled, egui::Button::new("Move")).clicked() {
+                            Some(Some(PathBuf::from(self.data_dir_input.trim())))
+                        } else if ui.add_enabled(enabled, egui::Button::new("Use default")).clicked() {
+                            Some(None)
+                        } else {
+                            None
+                        };
+
+                        if let Some(dir) = dir {
+                            self.data_dir_status = match data::move_to(&self.data(), dir.as_deref()) {
+                                Ok(path) => {
+                                    self.data_path = Some(path.clone());
+                                    self.data_modified = data::modified(&path);
+                                    *self.shared.data_path.lock().unwrap() = Some(path.clone());
+
+                                    self.data_dir_input = "".to_string();
+
+                                    format!("Moved the library to {}", path.display())
+                                }
+                                Err(err) => err.to_string(),
+                            };
+                        }
+                    });
+
+                    ui.label(&self.data_dir_status);
+
+                    ui.separator();
+
+                    ui.label("Tracking");
+
+                    ui.horizontal(|ui| {
+                        ui.label("Scan processes every");
+                        ui.add(egui::DragValue::new(&mut self.settings.scan_interval_secs).range(1..=600).suffix(" s"));
+                    });
+
+                    for scope in TrackingScope::ALL {
+                        ui.radio_value(&mut self.settings.tracking_scope, scope, scope.label());
+                    }
+
+                    ui.checkbox(&mut self.settings.use_denylist, "Skip system processes");
+
+                    ui.separator();
+
+                    ui.label("Launching");
+
+                    ui.label("Run games without a working directory in");
+
+                    for policy in WorkingDirPolicy::ALL {
+                        ui.radio_value(&mut self.settings.working_dir_policy, policy, policy.label());
+                    }
+
+                    ui.separator();
+
+                    ui.label("Playtime budget for all games");
+
+                    budget_controls(ui, &mut self.settings.budget);
+
+                    ui.horizontal(|ui| {
+                        ui.label("Warn at");
+
+                        for (i, minutes) in self.settings.budget_warnings.clone().iter().enumerate() {
+                            let mut value = *minutes;
+
+                            if ui.add(egui::DragValue::new(&mut value).range(1..=600).suffix(" min")).changed() {
+                                self.settings.budget_warnings[i] = value;
+                            }
+
+                            if ui.small_button("x").clicked() {
+                                self.settings.budget_warnings.remove(i);
+                            }
+                        }
+
+                        if ui.button("Add").clicked() {
+                            self.settings.budget_warnings.push(5);
+                        }
+
+                        ui.label("left");
+                    });
+
+                    ui.checkbox(&mut self.settings.budget_kill, "Stop games when their budget runs out");
+
+                    ui.separator();
+
+                    ui.label("Panic");
+
+                    for mode in PanicMode::ALL {
+                        ui.radio_value(&mut self.settings.panic_mode, mode, mode.label());
+                    }
+
+                    match self.settings.panic_mode {
+                        PanicMode::Kill => {
+                            ui.horizontal(|ui| {
+                                ui.label("Grace period before force killing");
+                                ui.add(egui::DragValue::new(&mut self.settings.panic_grace_secs).range(0..=60).suffix(" s"));
+                            });
+                        }
+                        PanicMode::Decoy => {
+                            ui.horizontal(|ui| {
+                                ui.label("Decoy program or document");
+                                ui.text_edit_singleline(&mut self.settings.decoy);
+                            });
+                        }
+                        PanicMode::Suspend | PanicMode::Hide => {}
+                    }
+
+                    ui.checkbox(&mut self.settings.keep_open, "Keep the launcher open after panic");
+
+                    ui.separator();
+
+                    ui.label("Auto panic when one of these processes starts");
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.trigger_input);
+
+                        if ui.button("Add").clicked() && !self.trigger_input.trim().is_empty() {
+                            self.settings.panic_processes.push(self.trigger_input.trim().to_string());
+                            self.settings.panic_processes.dedup();
+
+                            self.trigger_input = "".to_string();
+                        }
+                    });
+
+                    for (i, process) in self.settings.panic_processes.clone().iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(process);
+
+                            if ui.button("Remove").clicked() {
+                                self.settings.panic_processes.remove(i);
+                            }
+                        });
+                    }
+
+                    ui.label("Auto panic when class starts");
+
+                    for (i, hours) in self.settings.class_hours.clone().iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}: {} {}-{}", hours.name, hours.days.join(" "), hours.start, hours.end));
+
+                            if ui.button("Remove").clicked() {
+                                self.settings.class_hours.remove(i);
+                            }
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.class_name).hint_text("Name").desired_width(100.0));
+
+                        for (day, checked) in WEEKDAYS.iter().zip(self.class_days.iter_mut()) {
+                            ui.checkbox(checked, day.to_string());
+                        }
+
+                        ui.add(egui::TextEdit::singleline(&mut self.class_start).desired_width(50.0));
+                        ui.label("to");
+                        ui.add(egui::TextEdit::singleline(&mut self.class_end).desired_width(50.0));
+
+                        if ui.button("Add").clicked() {
+                            let days: Vec<Weekday> = WEEKDAYS
+                                .iter()
+                                .zip(self.class_days)
+                                .filter(|(_, checked)| *checked)
+                                .map(|(day, _)| *day)
+                                .collect();
+
+                            match ClassHours::new(&self.class_name, &days, &self.class_start, &self.class_end) {
+                                Ok(hours) => {
+                                    self.settings.class_hours.push(hours);
+
+                                    self.schedule_status = "".to_string();
+                                }
+                                Err(err) => self.schedule_status = err.to_string(),
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.ics_path).hint_text("timetable.ics"));
+
+                        if ui.button("Import .ics").clicked() {
+                            let imported = std::fs::read_to_string(self.ics_path.trim())
+                                .map_err(crate::core::Error::from)
+                                .and_then(|text| triggers::import_ics(&text));
+
+                            self.schedule_status = match imported {
+                                Ok((class_hours, events)) => {
+                                    let status = format!("Imported {} class hours and {} events", class_hours.len(), events.len());
+
+                                    self.settings.class_hours.extend(class_hours);
+                                    self.settings.events.extend(events);
+
+                                    status
+                                }
+                                Err(err) => err.to_string(),
+                            };
+                        }
+                    });
+
+                    if !self.settings.events.is_empty() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} imported events", self.settings.events.len()));
+
+                            if ui.button("Clear").clicked() {
+                                self.settings.events.clear();
+                            }
+                        });
+                    }
+
+                    ui.label(&self.schedule_status);
+
+                    if let Some(path) = triggers::log_path() {
+                        let log = triggers::read_log(&path, 20);
+
+                        if !log.is_empty() {
+                            egui::CollapsingHeader::new("Trigger log").show(ui, |ui| {
+                                for line in log {
+                                    ui.label(line);
+                                }
+                            });
+                        }
+                    }
+
+                    ui.separator();
+
+                    ui.label("Wrappers, run in this order around games that use them");
+
+                    for (i, wrapper) in self.settings.wrappers.clone().iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            let mut words = vec![wrapper.program.clone()];
+                            words.extend(wrapper.args.iter().cloned());
+
+                            let env: Vec<String> = wrapper.env.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+
+                            ui.label(format!("{}: {} {}", wrapper.name, env.join(" "), join_args(&words)));
+
+                            ui.checkbox(&mut self.settings.wrappers[i].global, "All games");
+
+                            if i > 0 && ui.button("Up").clicked() {
+                                self.settings.wrappers.swap(i, i - 1);
+                            }
+
+                            if ui.button("Remove").clicked() {
+                                self.settings.wrappers.remove(i);
+                            }
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("wrapper_preset")
+                            .selected_text("Preset")
+                            .show_ui(ui, |ui| {
+                                for preset in Wrapper::presets() {
+                                    if ui.selectable_label(false, &preset.name).clicked() {
+                                        let mut words = vec![preset.program.clone()];
+                                        words.extend(preset.args);
+
+                                        self.wrapper_name = preset.name;
+                                        self.wrapper_command = join_args(&words);
+                                        self.wrapper_env = "".to_string();
+                                    }
+                                }
+                            });
+
+                        ui.add(egui::TextEdit::singleline(&mut self.wrapper_name).hint_text("Name").desired_width(100.0));
+                        ui.add(egui::TextEdit::singleline(&mut self.wrapper_command).hint_text("gamescope -f --"));
+                        ui.add(egui::TextEdit::singleline(&mut self.wrapper_env).hint_text("KEY=value").desired_width(120.0));
+
+                        if ui.button("Add").clicked() {
+                            let mut words = split_args(&self.wrapper_command);
+
+                            let wrapper = Wrapper {
+                                name: self.wrapper_name.trim().to_string(),
+                                program: match words.is_empty() {
+                                    true => "".to_string(),
+                                    false => words.remove(0),
+                                },
+                                args: words,
+                                env: split_args(&self.wrapper_env)
+                                    .iter()
+                                    .map(|var| match var.split_once('=') {
+                                        Some((key, value)) => (key.to_string(), value.to_string()),
+                                        None => (var.to_string(), "".to_string()),
+                                    })
+                                    .collect(),
+                                global: false,
+                            };
+
+                            match wrapper.validate(&self.settings.wrappers) {
+                                Ok(()) => {
+                                    self.settings.wrappers.push(wrapper);
+
+                                    self.wrapper_name = "".to_string();
+                                    self.wrapper_command = "".to_string();
+                                    self.wrapper_env = "".to_string();
+                                    self.wrapper_status = "".to_string();
+                                }
+                                Err(err) => self.wrapper_status = err.to_string(),
+                            }
+                        }
+                    });
+
+                    ui.label(&self.wrapper_status);
+
+                    ui.separator();
+
+                    ui.label("Wine and Proton, for running Windows games");
+
+                    for (i, runner) in self.settings.runners.clone().iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{} ({}): {}", runner.name, runner.kind.label(), runner.path.display()));
+
+                            if ui.button("Remove").clicked() {
+                                self.settings.runners.remove(i);
+                            }
+                        });
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.runner_name).hint_text("Name").desired_width(100.0));
+
+                        for kind in RunnerKind::ALL {
+                            ui.radio_value(&mut self.runner_kind, kind, kind.label());
+                        }
+
+                        ui.add(egui::TextEdit::singleline(&mut self.runner_path).hint_text("/usr/bin/wine or .../proton"));
+
+                        if ui.button("Add").clicked() {
+                            let runner = Runner {
+                                name: self.runner_name.trim().to_string(),
+                                kind: self.runner_kind,
+                                path: self.runner_path.trim().into(),
+                            };
+
+                            match runner.validate(&self.settings.runners) {
+                                Ok(()) => {
+                                    self.settings.runners.push(runner);
+
+                                    self.runner_name = "".to_string();
+                                    self.runner_path = "".to_string();
+                                    self.runner_status = "".to_string();
+                                }
+                                Err(err) => self.runner_status = err.to_string(),
+                            }
+                        }
+                    });
+
+                    ui.label(&self.runner_status);
+
+                    ui.separator();
+
+                    ui.label("Locked mode");
+
+                    ui.label("Ask for a PIN or passphrase before settings are changed, games are added, edited or removed and recorded time is changed");
+
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.new_pin).password(true).hint_text("New PIN"));
+                        ui.add(egui::TextEdit::singleline(&mut self.confirm_pin).password(true).hint_text("Again"));
+
+                        let label = match self.settings.lock.is_set() {
+                            true => "Change",
+                            false => "Set",
+                        };
+
+                        if ui.button(label).clicked() {
+                            let new_lock = match self.new_pin == self.confirm_pin {
+                                true => Lock::new(&self.new_pin),
+                                false => Err(crate::core::Error::Lock("the two didn't match".to_string())),
+                            };
+
+                            self.lock_status = match new_lock {
+                                Ok(new_lock) => {
+                                    self.settings.lock = new_lock;
+                                    self.unlocked = Some(Instant::now());
+
+                                    lock::audit(now(), "PIN set");
+
+                                    "Locked mode is on".to_string()
+                                }
+                                Err(err) => err.to_string(),
+                            };
+
+                            self.new_pin = "".to_string();
+                            self.confirm_pin = "".to_string();
+                        }
+
+                        if self.settings.lock.is_set() && ui.button("Remove").clicked() {
+                            self.settings.lock = Lock::default();
+
+                            lock::audit(now(), "PIN removed");
+
+                            self.lock_status = "Locked mode is off".to_string();
+                        }
+                    });
+
+                    ui.label(&self.lock_status);
+                });
+
+                if self.settings != settings {
+                    self.update_filter();
+                }
+            }
 
         });
     }
 }
 
-fn format_time(time: &u64) -> String {
-    if *time < 60 {
-        format!("{} seconds", time)
-    } else if *time < 3600 {
-        format!("{} minutes", time / 60)
-    } else {
-        format!("{} hours", time / 3600)
+/// What the panic routine needs, shared with the control thread since the
+/// window isn't updated while it is minimized.
+#[derive(Clone, Default)]
+struct Shared {
+    launcher: Arc<Mutex<Launcher>>,
+    tracker: Tracker,
+
+    /// Copies of the app's, see `GameLunch::update_filter`.
+    library: Arc<Mutex<Library>>,
+    settings: Arc<Mutex<Settings>>,
+
+    restore: Arc<Mutex<Option<Snapshot>>>,
+    status: Arc<Mutex<String>>,
+
+    /// Where to save before exiting, unless the library failed to load.
+    data_path: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Shared {
+    /// Stops the game with id `id` like panic's kill mode would, and
+    /// returns a status line.
+    fn kill_game(&self, id: Uuid) -> String {
+        let library = self.library.lock().unwrap().clone();
+        let grace = Duration::from_secs(self.settings.lock().unwrap().panic_grace_secs);
+
+        let processes = Scanner::default().scan();
+
+        // the launcher is only locked briefly, the UI and reaper keep going
+        // during the grace period
+        let killed = crate::core::panic::kill_game(&self.launcher, &library, &processes, id, grace);
+
+        format!("stopped {} processes", killed.len())
     }
+
+    /// Runs the panic routine and returns its status line. Exits the
+    /// launcher unless it should be kept open.
+    fn panic(&self, ctx: &egui::Context) -> String {
+        let library = self.library.lock().unwrap().clone();
+        let settings = self.settings.lock().unwrap().clone();
+
+        let outcome = crate::core::panic::panic(
+            &self.launcher,
+            &library,
+            &self.tracker.launched_pids(),
+            &settings,
+        );
+
+        let status = match outcome {
+            Ok(outcome) => {
+                if let Some(snapshot) = outcome.restore() {
+                    *self.restore.lock().unwrap() = Some(snapshot.clone());
+                }
+
+                outcome.to_string()
+            }
+            Err(err) => {
+                log::warn!("Panic failed: {}", err);
+
+                err.to_string()
+            }
+        };
+
+        if !settings.keep_open {
+            // exiting right away skips eframe's save
+            if let Some(path) = self.data_path.lock().unwrap().as_ref() {
+                let data = Data::new(
+                    library,
+                    &self.tracker.history(),
+                    settings,
+                    self.restore.lock().unwrap().clone(),
+                );
+
+                let _ = data.save(path);
+            }
+
+            std::process::exit(0);
+        }
+
+        if matches!(settings.panic_mode, PanicMode::Hide | PanicMode::Decoy) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+        }
+
+        status.clone_into(&mut self.status.lock().unwrap());
+
+        status
+    }
+
+    fn resume(&self) -> String {
+        let resumed = crate::core::panic::resume_games(
+            &self.launcher.lock().unwrap(),
+            &self.library.lock().unwrap(),
+            &Scanner::default().scan(),
+            &self.tracker.launched_pids(),
+        );
+
+        let status = format!("Resumed {} processes", resumed.len());
+
+        status.clone_into(&mut self.status.lock().unwrap());
+
+        status
+    }
+}
+
+/// Daily and weekly minutes of `budget`, each behind a checkbox.
+fn budget_controls(ui: &mut egui::Ui, budget: &mut Budget) {
+    ui.horizontal(|ui| {
+        for (label, minutes) in [
+            ("Daily", &mut budget.daily_minutes),
+            ("Weekly", &mut budget.weekly_minutes),
+        ] {
+            let mut enabled = minutes.is_some();
+
+            if ui.checkbox(&mut enabled, label).changed() {
+                *minutes = enabled.then_some(60);
+            }
+
+            if let Some(minutes) = minutes {
+                ui.add(
+                    egui::DragValue::new(minutes)
+                        .range(0..=10080)
+                        .suffix(" min"),
+                );
+            }
+        }
+    });
 }
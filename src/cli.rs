@@ -0,0 +1,1091 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::core::budget::Budgets;
+use crate::core::control::{self, Command};
+use crate::core::data::{self, Data};
+use crate::core::detect::Plan;
+use crate::core::format::{format_date, format_time};
+use crate::core::launcher::Launcher;
+use crate::core::lock::{self, Lock};
+use crate::core::matching::MatchRule;
+use crate::core::panic::Outcome;
+use crate::core::runner::{self, Runner, RunnerKind, WineConfig};
+use crate::core::scope::Filter;
+use crate::core::session::now;
+use crate::core::target::LaunchTarget;
+use crate::core::tracker::Scanner;
+use crate::core::wrappers;
+use crate::core::{Error, Game, Result};
+
+const USAGE: &str = "\
+Usage: gamelunch [COMMAND]
+
+Without a command the launcher window is opened.
+
+Commands:
+  list [--json]                    List games and their playtime
+  add <name> <author> <location> [OPTION]...
+                                   Add a game to the library. The location is
+                                   an executable, steam:<app id>, flatpak:<app
+                                   id>, shell:<command> or a URL. Options:
+                                     --rule <rule>    exe_name:<name>, exe_path:<glob>,
+                                                      command_line:<regex> or descendants
+                                     --arg <arg>      pass an argument, repeatable
+                                     --env <KEY=VAL>  set an environment variable
+                                     --unset <KEY>    remove an environment variable
+                                     --cwd <dir>      run in dir instead of the
+                                                      folder of the executable
+                                     --rom <path>     the location is an emulator,
+                                                      start it with this ROM
+                                     --wrapper <name> run through a wrapper profile,
+                                                      repeatable, outermost first
+                                     --runner <name>  run through Wine or Proton
+                                     --prefix <dir>   use dir as the Wine prefix
+                                     --prefix-env <KEY=VAL>
+                                                      set a variable for the prefix
+                                     --daily <minutes>, --weekly <minutes>
+                                                      limit playtime, none for no
+                                                      limit
+  edit <name> [OPTION]...          Change a game, keeping its playtime. Takes
+                                   --name, --author, --location and the
+                                   options of add; --rule, --arg and --wrapper
+                                   replace the old lists
+  remove <name>                    Move a game and its playtime to the trash
+  trash [restore <name>|empty]     List removed games, put one back or delete
+                                   them for good. Games are kept for 30 days
+  launch <name> [--dry-run]        Launch a game, or print the command that
+                                   would run. Refused once its budget is used up
+  budget [--daily <minutes>] [--weekly <minutes>]
+                                   Print the playtime left of every game, or set
+                                   the budget for all games
+  runner add <name> <wine|proton> <path>
+                                   Add a Wine binary or Proton script to run
+                                   Windows games with
+  prefix <create|delete|path> <name>
+                                   Set up, delete or print the Wine prefix of
+                                   a game
+  stats [--json]                   Print time tracked for every process
+  prune                            Delete time of processes outside the tracking scope
+  panic                            Kill or suspend all games, or open the decoy,
+                                   depending on the panic mode setting. Handled
+                                   by the launcher window if it is open
+  resume                           Resume games suspended by panic
+  restore                          Relaunch the games that were running when
+                                   panic last killed them
+  lock [set|remove]                Print whether locked mode is on, or set or
+                                   remove its PIN or passphrase. Once set,
+                                   add, edit, remove, trash restore and
+                                   empty, setting budgets, runner add,
+                                   prefix delete and prune ask for it
+  help                             Print this message";
+
+/// Runs a command line subcommand and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    match execute(args) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("gamelunch: {}", err);
+            1
+        }
+    }
+}
+
+fn execute(args: &[String]) -> Result<()> {
+    let Some((command, rest)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+
+        return Err(Error::Usage(String::new()));
+    };
+
+    match (command.as_str(), rest) {
+        ("list", options) => {
+            let json = parse_json(options)?;
+            let (_, data) = open()?;
+
+            print!("{}", list(&data, json));
+        }
+
+        ("add", [name, author, location, options @ ..]) => {
+            let mut game = Game {
+                name: name.clone(),
+                author: author.clone(),
+                location: location.parse()?,
+                ..Default::default()
+            };
+
+            parse_options(&mut game, options)?;
+
+            let (path, mut data) = open()?;
+
+            unlock(&data, &format!("add {}", name))?;
+
+            wrappers::resolve(&data.settings.wrappers, &game)?;
+            runner::resolve(&data.settings.runners, &game)?;
+
+            let detected = detect(&game);
+
+            data.games.add(game)?;
+
+            data.save(&path)?;
+
+            println!("Added {}", name);
+
+            report(detected);
+        }
+
+        ("edit", [name, options @ ..]) => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, &format!("edit {}", name))?;
+
+            let mut game = data
+                .games
+                .get(name)
+                .ok_or_else(|| Error::GameNotFound(name.clone()))?
+                .clone();
+            let mut rest = Vec::new();
+
+            for option in options.chunks(2) {
+                match option {
+                    [flag, value] if flag == "--name" => game.name = value.clone(),
+                    [flag, value] if flag == "--author" => game.author = value.clone(),
+                    [flag, value] if flag == "--location" => {
+                        game.location = match (value.parse()?, &game.location) {
+                            // an emulator keeps its ROM unless --rom is given
+                            (
+                                LaunchTarget::Executable(emulator),
+                                LaunchTarget::Emulator { rom, .. },
+                            ) => LaunchTarget::Emulator {
+                                emulator,
+                                rom: rom.clone(),
+                            },
+                            (location, _) => location,
+                        };
+                    }
+                    _ => rest.extend_from_slice(option),
+                }
+            }
+
+            for flag in rest.iter().step_by(2) {
+                match flag.as_str() {
+                    "--rule" => game.rules.clear(),
+                    "--arg" => game.args.clear(),
+                    "--wrapper" => game.wrappers.clear(),
+                    _ => {}
+                }
+            }
+
+            parse_options(&mut game, &rest)?;
+
+            wrappers::resolve(&data.settings.wrappers, &game)?;
+            runner::resolve(&data.settings.runners, &game)?;
+
+            let detected = detect(&game);
+
+            let id = game.id;
+            let old = data.games.edit(id, game)?;
+            let game = data.games.find(id).cloned().unwrap_or_default();
+            let mut history = data.history();
+
+            history.rename(&old.key(), &game.key());
+
+            data.set_history(&history);
+            data.save(&path)?;
+
+            println!("Updated {}", game.name);
+
+            if old.location != game.location {
+                report(detected);
+            }
+        }
+
+        ("remove", [name]) => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, &format!("remove {}", name))?;
+
+            let mut history = data.history();
+
+            data.games.remove_by_name(name, &mut history, now())?;
+
+            data.set_history(&history);
+            data.save(&path)?;
+
+            println!(
+                "Moved {} to the trash, undo with gamelunch trash restore",
+                name
+            );
+        }
+
+        ("trash", []) => {
+            let (_, data) = open()?;
+
+            for trashed in data.games.trash() {
+                println!(
+                    "{:<24} {:<16} {:<12} removed {}",
+                    trashed.game.name,
+                    trashed.game.author,
+                    format_time(&trashed.seconds()),
+                    format_date(trashed.removed)
+                );
+            }
+        }
+
+        ("trash", [action, name]) if action == "restore" => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, &format!("restore {}", name))?;
+
+            let id = data
+                .games
+                .trash()
+                .iter()
+                .rev()
+                .find(|trashed| &trashed.game.name == name)
+                .map(|trashed| trashed.game.id)
+                .ok_or_else(|| Error::GameNotFound(name.clone()))?;
+
+            let mut history = data.history();
+
+            data.games.restore(id, &mut history)?;
+
+            data.set_history(&history);
+            data.save(&path)?;
+
+            println!("Restored {}", name);
+        }
+
+        ("trash", [action]) if action == "empty" => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, "empty the trash")?;
+
+            let deleted = data.games.empty_trash(None);
+
+            data.save(&path)?;
+
+            println!("Deleted {} games", deleted);
+        }
+
+        ("launch", [name, options @ ..]) if options.iter().all(|option| option == "--dry-run") => {
+            let (_, data) = open()?;
+
+            println!("{}", launch(&data, name, !options.is_empty(), now())?);
+        }
+
+        ("budget", []) => {
+            let (_, data) = open()?;
+            let (library, history, settings) = data.into_parts();
+
+            let budgets = Budgets::new(&settings, &library);
+
+            for game in library.games() {
+                let left = match budgets.limits(&history, game.id, now()).first() {
+                    Some(limit) => format!("{} left of the {}", format_time(&limit.left()), limit),
+                    None => "no budget".to_string(),
+                };
+
+                println!("{:<24} {}", game.name, left);
+            }
+        }
+
+        ("budget", options) => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, "set the budget for all games")?;
+
+            for option in options.chunks(2) {
+                match option {
+                    [flag, minutes] if flag == "--daily" => {
+                        data.settings.budget.daily_minutes = parse_minutes(minutes)?
+                    }
+                    [flag, minutes] if flag == "--weekly" => {
+                        data.settings.budget.weekly_minutes = parse_minutes(minutes)?
+                    }
+                    _ => return Err(Error::Usage(option.join(" "))),
+                }
+            }
+
+            data.save(&path)?;
+
+            println!("Set the budget for all games");
+        }
+
+        ("stats", options) => {
+            let json = parse_json(options)?;
+            let (_, data) = open()?;
+
+            print!("{}", stats(&data, json));
+        }
+
+        ("prune", []) => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, "prune recorded time")?;
+
+            let mut history = data.history();
+
+            let filter = Filter::new(&data.settings, &data.games);
+            let removed = history.prune(|process| filter.tracks(process));
+
+            data.set_history(&history);
+            data.save(&path)?;
+
+            println!("Removed {} processes", removed);
+        }
+
+        ("panic", []) => {
+            if forward(Command::Panic) {
+                return Ok(());
+            }
+
+            let (path, mut data) = open()?;
+
+            let outcome = crate::core::panic::panic(
+                &Mutex::new(Launcher::default()),
+                &data.games,
+                &[],
+                &data.settings,
+            )?;
+
+            if let Outcome::Killed(processes, _) | Outcome::Suspended(processes) = &outcome {
+                for process in processes {
+                    println!("{}", process);
+                }
+            }
+
+            println!("{}", outcome);
+
+            if let Some(snapshot) = outcome.restore() {
+                data.restore = Some(snapshot.clone());
+
+                data.save(&path)?;
+            }
+        }
+
+        ("resume", []) => {
+            if forward(Command::Resume) {
+                return Ok(());
+            }
+
+            let (_, data) = open()?;
+
+            let resumed = crate::core::panic::resume_games(
+                &Launcher::default(),
+                &data.games,
+                &Scanner::default().scan(),
+                &[],
+            );
+
+            for process in &resumed {
+                println!("{}", process);
+            }
+
+            println!("Resumed {} processes", resumed.len());
+        }
+
+        ("restore", []) => {
+            let (path, mut data) = open()?;
+
+            let Some(mut snapshot) = data.restore.take() else {
+                println!("Nothing to restore");
+
+                return Ok(());
+            };
+
+            let budgets = Budgets::new(&data.settings, &data.games);
+            let history = data.history();
+            let mut launcher = launcher(&data);
+
+            for result in snapshot.relaunch(&mut launcher, &data.games, &budgets, &history, now()) {
+                match result {
+                    Ok((game, _)) => println!("Launched {}", game.name),
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+
+            // games that failed to start are kept for the next try
+            if !snapshot.games.is_empty() {
+                data.restore = Some(snapshot);
+            }
+
+            data.save(&path)?;
+        }
+
+        ("runner", [action, name, kind, runner_path]) if action == "add" => {
+            let kind = match kind.as_str() {
+                "wine" => RunnerKind::Wine,
+                "proton" => RunnerKind::Proton,
+                _ => return Err(Error::Usage(kind.clone())),
+            };
+
+            let (path, mut data) = open()?;
+
+            unlock(&data, &format!("add runner {}", name))?;
+
+            let runner = Runner {
+                name: name.clone(),
+                kind,
+                path: runner_path.into(),
+            };
+
+            runner.validate(&data.settings.runners)?;
+
+            data.settings.runners.push(runner);
+
+            data.save(&path)?;
+
+            println!("Added {}", name);
+        }
+
+        ("prefix", [action, name]) => {
+            let (_, data) = open()?;
+
+            // deleting a prefix deletes the game's saves with it
+            if action == "delete" {
+                unlock(&data, &format!("delete the prefix of {}", name))?;
+            }
+
+            let game = data
+                .games
+                .get(name)
+                .ok_or_else(|| Error::GameNotFound(name.clone()))?;
+
+            let launcher = launcher(&data);
+
+            let Some(runner) = launcher.runner(game)? else {
+                return Err(Error::Prefix(format!("{} runs natively", name)));
+            };
+
+            let prefix = runner::prefix(game).ok_or_else(|| {
+                Error::Prefix("no data directory to keep prefixes in".to_string())
+            })?;
+
+            match action.as_str() {
+                "path" => println!("{}", prefix.display()),
+                "create" => {
+                    runner.create_prefix(&prefix)?;
+
+                    println!("Created {}", prefix.display());
+                }
+                "delete" => {
+                    runner::delete_prefix(&prefix)?;
+
+                    println!("Deleted {}", prefix.display());
+                }
+                _ => return Err(Error::Usage(action.clone())),
+            }
+        }
+
+        ("lock", []) => {
+            let (_, data) = open()?;
+            let guesses = lock::guesses();
+
+            match data.settings.lock.is_set() {
+                true => println!(
+                    "Locked mode is on, {} wrong guesses in a row",
+                    guesses.failures
+                ),
+                false => println!("Locked mode is off"),
+            }
+
+            let wait = guesses.wait(now());
+
+            if wait > 0 {
+                println!("The next guess is allowed in {}", format_time(&wait));
+            }
+        }
+
+        ("lock", [action]) if action == "set" => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, "change the PIN")?;
+
+            let secret = read_secret("New PIN or passphrase: ")?;
+
+            if read_secret("Again: ")? != secret {
+                return Err(Error::Lock("the two didn't match".to_string()));
+            }
+
+            data.settings.lock = Lock::new(&secret)?;
+
+            data.save(&path)?;
+
+            lock::audit(now(), "PIN set");
+
+            println!("Locked mode is on");
+        }
+
+        ("lock", [action]) if action == "remove" => {
+            let (path, mut data) = open()?;
+
+            unlock(&data, "remove the PIN")?;
+
+            data.settings.lock = Lock::default();
+
+            data.save(&path)?;
+
+            lock::audit(now(), "PIN removed");
+
+            println!("Locked mode is off");
+        }
+
+        ("help" | "--help" | "-h", _) => println!("{}", USAGE),
+
+        _ => {
+            eprintln!("{}", USAGE);
+
+            return Err(Error::Usage(args.join(" ")));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `options` of `list` or `stats` ask for JSON. `--json` is the
+/// only one they take.
+fn parse_json(options: &[String]) -> Result<bool> {
+    match options {
+        [] => Ok(false),
+        [option] if option == "--json" => Ok(true),
+        _ => Err(Error::Usage(options.join(" "))),
+    }
+}
+
+/// Asks for the PIN or passphrase before `action` when locked mode is on.
+/// Wrong guesses count across runs, see `lock::Guesses`.
+fn unlock(data: &Data, action: &str) -> Result<()> {
+    if !data.settings.lock.is_set() {
+        return Ok(());
+    }
+
+    let secret = read_secret("PIN or passphrase: ")?;
+
+    // nothing typed, or no input at all, isn't a guess
+    if secret.is_empty() {
+        return Err(Error::Lock(format!(
+            "{} needs the PIN or passphrase",
+            action
+        )));
+    }
+
+    data.settings.lock.unlock(&secret, action, now())
+}
+
+/// Reads a line without echoing it when stdin is a terminal. Piped input is
+/// read as is, for scripts.
+fn read_secret(prompt: &str) -> Result<String> {
+    let terminal = std::io::stdin().is_terminal();
+
+    if terminal {
+        eprint!("{}", prompt);
+    }
+
+    let echo = terminal.then(hide_input);
+    let mut secret = String::new();
+
+    std::io::stdin().read_line(&mut secret)?;
+
+    if echo.is_some() {
+        eprintln!();
+    }
+
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Turns terminal echo back on when dropped.
+#[cfg(unix)]
+struct Echo(Option<libc::termios>);
+
+#[cfg(unix)]
+impl Drop for Echo {
+    fn drop(&mut self) {
+        if let Some(termios) = &self.0 {
+            // SAFETY: termios was filled in by tcgetattr and outlives the call
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) };
+        }
+    }
+}
+
+#[cfg(unix)]
+fn hide_input() -> Echo {
+    // SAFETY: termios is plain old data, all zeroes is a valid value
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+
+    // SAFETY: termios is a valid place for tcgetattr to write to
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+        return Echo(None);
+    }
+
+    let mut hidden = termios;
+
+    hidden.c_lflag &= !libc::ECHO;
+
+    // SAFETY: hidden is a copy of what tcgetattr filled in
+    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden) };
+
+    Echo(Some(termios))
+}
+
+#[cfg(not(unix))]
+fn hide_input() {}
+
+/// Hands `command` to the running launcher, if there is one, so it acts on
+/// the games it launched. Returns whether it did.
+fn forward(command: Command) -> bool {
+    let Some(path) = control::socket_path() else {
+        return false;
+    };
+
+    match control::send(&path, command) {
+        Ok(reply) if reply.is_empty() => println!("Sent {} to the launcher", command),
+        Ok(reply) => println!("{}", reply),
+        Err(_) => return false,
+    }
+
+    true
+}
+
+/// A launcher that runs games through the wrappers and runners in the
+/// settings.
+fn launcher(data: &Data) -> Launcher {
+    let mut launcher = Launcher::default();
+
+    launcher.configure(&data.settings);
+
+    launcher
+}
+
+/// How the game's executable will be run, if it has one on disk.
+fn detect(game: &Game) -> Option<Result<Plan>> {
+    game.location
+        .executable()
+        .filter(|program| program.is_file())
+        .map(|program| Plan::new(program, game.wine.is_some()))
+}
+
+fn report(detected: Option<Result<Plan>>) {
+    match detected {
+        Some(Ok(plan)) => println!("Detected {}", plan),
+        Some(Err(err)) => eprintln!("Warning: {}", err),
+        None => {}
+    }
+}
+
+fn parse_options(game: &mut Game, options: &[String]) -> Result<()> {
+    for option in options.chunks(2) {
+        match option {
+            [flag, rule] if flag == "--rule" => game.rules.push(rule.parse::<MatchRule>()?),
+            [flag, arg] if flag == "--arg" => game.args.push(arg.clone()),
+            [flag, var] if flag == "--env" => {
+                let (key, value) = var
+                    .split_once('=')
+                    .ok_or_else(|| Error::Usage(option.join(" ")))?;
+
+                game.env.insert(key.to_string(), Some(value.to_string()));
+            }
+            [flag, key] if flag == "--unset" => {
+                game.env.insert(key.clone(), None);
+            }
+            [flag, dir] if flag == "--cwd" => game.working_dir = Some(dir.into()),
+            [flag, rom] if flag == "--rom" => match &game.location {
+                LaunchTarget::Executable(emulator) | LaunchTarget::Emulator { emulator, .. } => {
+                    game.location = LaunchTarget::Emulator {
+                        emulator: emulator.clone(),
+                        rom: rom.into(),
+                    };
+                }
+                _ => return Err(Error::Usage(option.join(" "))),
+            },
+            [flag, name] if flag == "--wrapper" => game.wrappers.push(name.clone()),
+            [flag, name] if flag == "--runner" => {
+                game.wine.get_or_insert_with(WineConfig::default).runner = name.clone();
+            }
+            [flag, dir] if flag == "--prefix" => {
+                game.wine.get_or_insert_with(WineConfig::default).prefix = Some(dir.into());
+            }
+            [flag, minutes] if flag == "--daily" => {
+                game.budget.daily_minutes = parse_minutes(minutes)?
+            }
+            [flag, minutes] if flag == "--weekly" => {
+                game.budget.weekly_minutes = parse_minutes(minutes)?
+            }
+            [flag, var] if flag == "--prefix-env" => {
+                let (key, value) = var
+                    .split_once('=')
+                    .ok_or_else(|| Error::Usage(option.join(" ")))?;
+
+                game.wine
+                    .get_or_insert_with(WineConfig::default)
+                    .env
+                    .insert(key.to_string(), value.to_string());
+            }
+            _ => return Err(Error::Usage(option.join(" "))),
+        }
+    }
+
+    Ok(())
+}
+
+/// Minutes of a budget, `none` for no limit.
+fn parse_minutes(minutes: &str) -> Result<Option<u64>> {
+    match minutes {
+        "none" => Ok(None),
+        _ => minutes
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Usage(minutes.to_string())),
+    }
+}
+
+/// Launches the game named `name` if its budget has time left at `now`, or
+/// with `dry_run` gives the command that would run.
+fn launch(data: &Data, name: &str, dry_run: bool, now: u64) -> Result<String> {
+    let game = data
+        .games
+        .get(name)
+        .ok_or_else(|| Error::GameNotFound(name.to_string()))?;
+
+    let mut launcher = launcher(data);
+
+    if dry_run {
+        return Ok(launcher.command_line(game, &game.args)?.to_string());
+    }
+
+    Budgets::new(&data.settings, &data.games).allow(&data.history(), game.id, now)?;
+
+    Ok(match launcher.launch(game)? {
+        Some(_) => format!("Launched {}", name),
+        None => format!("Opened {}", name),
+    })
+}
+
+/// The games with their playtime, a line each or as a JSON array.
+fn list(data: &Data, json: bool) -> String {
+    let history = data.history();
+
+    if json {
+        let games: Vec<_> = data
+            .games
+            .games()
+            .iter()
+            .map(|game| {
+                let key = game.key();
+
+                serde_json::json!({
+                    "id": game.id,
+                    "name": game.name,
+                    "author": game.author,
+                    "location": game.location.to_string(),
+                    "kind": game.location.kind(),
+                    "rules": game.rules.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                    "args": game.args,
+                    "env": game.env,
+                    "working_dir": game.working_dir,
+                    "wrappers": game.wrappers,
+                    "wine": game.wine,
+                    "budget": game.budget,
+                    "seconds": history.total(&key),
+                    "week_seconds": history.this_week(&key),
+                    "last_played": history.last_played(&key),
+                    "days": history
+                        .per_day(&key, 7)
+                        .into_iter()
+                        .map(|(day, time)| (day.to_string(), time.into()))
+                        .collect::<serde_json::Map<_, _>>(),
+                })
+            })
+            .collect();
+
+        format!("{}\n", serde_json::Value::Array(games))
+    } else {
+        data.games
+            .games()
+            .iter()
+            .map(|game| {
+                let key = game.key();
+
+                format!(
+                    "{:<24} {:<16} {:<12} {:<12} {:<16} {}\n",
+                    game.name,
+                    game.author,
+                    format_time(&history.total(&key)),
+                    format_time(&history.this_week(&key)),
+                    history
+                        .last_played(&key)
+                        .map(format_date)
+                        .unwrap_or_default(),
+                    game.location
+                )
+            })
+            .collect()
+    }
+}
+
+/// Time tracked per process, most played first, with games marked by `*`.
+fn stats(data: &Data, json: bool) -> String {
+    let mut time: Vec<_> = data.history().totals().into_iter().collect();
+
+    time.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    if json {
+        let map: serde_json::Map<_, _> = time
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+
+        format!("{}\n", serde_json::Value::Object(map))
+    } else {
+        let games: Vec<_> = data.games.games().iter().map(|game| game.key()).collect();
+
+        time.into_iter()
+            .map(|(key, value)| {
+                let marker = if games.contains(&key) { "*" } else { " " };
+
+                format!("{} {:<32} {}\n", marker, key, format_time(&value))
+            })
+            .collect()
+    }
+}
+
+fn open() -> Result<(PathBuf, Data)> {
+    let path = data::default_path()
+        .ok_or_else(|| Error::Storage("could not find a data directory".to_string()))?;
+
+    let data = Data::open(&path, data::legacy)?;
+
+    Ok((path, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::game;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_fill_in_the_game() {
+        let mut game = Game::default();
+
+        let options = strings(&[
+            "--rule",
+            "exe_name:game",
+            "--rule",
+            "descendants",
+            "--arg",
+            "--fast",
+            "--env",
+            "LANG=C=1",
+            "--unset",
+            "DISPLAY",
+            "--cwd",
+            "/games",
+            "--wrapper",
+            "gamescope",
+            "--runner",
+            "Proton",
+            "--prefix-env",
+            "DXVK_HUD=1",
+            "--daily",
+            "60",
+            "--weekly",
+            "none",
+        ]);
+
+        parse_options(&mut game, &options).unwrap();
+
+        assert_eq!(
+            game.rules,
+            [
+                MatchRule::ExeName("game".to_string()),
+                MatchRule::Descendants
+            ]
+        );
+        assert_eq!(game.args, ["--fast"]);
+        assert_eq!(game.env["LANG"].as_deref(), Some("C=1"));
+        assert_eq!(game.env["DISPLAY"], None);
+        assert_eq!(game.working_dir, Some(PathBuf::from("/games")));
+        assert_eq!(game.wrappers, ["gamescope"]);
+
+        let wine = game.wine.unwrap();
+
+        assert_eq!(wine.runner, "Proton");
+        assert_eq!(wine.prefix, None);
+        assert_eq!(wine.env["DXVK_HUD"], "1");
+        assert_eq!(game.budget.daily_minutes, Some(60));
+        assert_eq!(game.budget.weekly_minutes, None);
+    }
+
+    #[test]
+    fn rom_turns_the_location_into_an_emulator() {
+        let mut game = Game {
+            location: "/usr/bin/mgba".parse().unwrap(),
+            ..Default::default()
+        };
+
+        parse_options(&mut game, &strings(&["--rom", "/roms/game.gba"])).unwrap();
+
+        assert_eq!(
+            game.location,
+            LaunchTarget::Emulator {
+                emulator: "/usr/bin/mgba".into(),
+                rom: "/roms/game.gba".into(),
+            }
+        );
+
+        let mut game = Game {
+            location: LaunchTarget::Steam(504230),
+            ..Default::default()
+        };
+
+        assert!(parse_options(&mut game, &strings(&["--rom", "/roms/game.gba"])).is_err());
+    }
+
+    #[test]
+    fn refuses_bad_options() {
+        for options in [
+            &["--arg"][..],
+            &["--color", "red"],
+            &["--env", "LANG"],
+            &["--daily", "an hour"],
+            &["--rule", "window_title:game"],
+        ] {
+            assert!(
+                parse_options(&mut Game::default(), &strings(options)).is_err(),
+                "{:?}",
+                options
+            );
+        }
+    }
+
+    fn library() -> Data {
+        let mut data = Data::default();
+
+        for (name, location) in [
+            ("Celeste", "steam:504230"),
+            ("Hollow Knight", "steam:367520"),
+        ] {
+            data.games
+                .add(game(name, location.parse().unwrap()))
+                .unwrap();
+        }
+
+        data.imported_time = [
+            ("steam://rungameid/504230".to_string(), 2 * 60 * 60),
+            ("/usr/bin/firefox".to_string(), 60),
+        ]
+        .into();
+
+        data
+    }
+
+    #[test]
+    fn lists_games_with_their_playtime() {
+        let data = library();
+
+        assert_eq!(
+            list(&data, false),
+            format!(
+                "{:<24} Someone          2 hours      0 seconds    {:<16} steam://rungameid/504230\n\
+                 {:<24} Someone          0 seconds    0 seconds    {:<16} steam://rungameid/367520\n",
+                "Celeste", "", "Hollow Knight", ""
+            )
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&list(&data, true)).unwrap();
+
+        assert_eq!(json[0]["id"], data.games.games()[0].id.to_string());
+        assert_eq!(json[0]["name"], "Celeste");
+        assert_eq!(json[0]["kind"], "steam");
+        assert_eq!(json[0]["seconds"], 2 * 60 * 60);
+        assert_eq!(json[0]["last_played"], serde_json::Value::Null);
+        assert_eq!(json[0]["days"].as_object().unwrap().len(), 7);
+        assert_eq!(json[1]["seconds"], 0);
+        assert_eq!(json.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stats_mark_games() {
+        let data = library();
+
+        assert_eq!(
+            stats(&data, false),
+            "* steam://rungameid/504230         2 hours\n  /usr/bin/firefox                 1 minutes\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&stats(&data, true)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({ "steam://rungameid/504230": 7200, "/usr/bin/firefox": 60 })
+        );
+        assert_eq!(stats(&Data::default(), false), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_checks_the_budget() {
+        let mut data = library();
+        let mut script = Game {
+            args: strings(&["--fast"]),
+            ..game("Script", "shell:exit 0".parse().unwrap())
+        };
+
+        script.budget.daily_minutes = Some(1);
+        data.games.add(script).unwrap();
+
+        assert_eq!(
+            launch(&data, "Script", true, 0).unwrap(),
+            r#"sh -c 'exit 0 "$@"' sh --fast"#
+        );
+        assert!(matches!(
+            launch(&data, "Missing", true, 0),
+            Err(Error::GameNotFound(_))
+        ));
+
+        let now = crate::core::session::day_start(chrono::Local::now().date_naive()) + 60 * 60;
+
+        assert_eq!(
+            launch(&data, "Script", false, now).unwrap(),
+            "Launched Script"
+        );
+
+        let mut history = data.history();
+
+        history.record_for_test("shell:exit 0", now - 60, 60);
+        data.set_history(&history);
+
+        assert!(matches!(
+            launch(&data, "Script", false, now),
+            Err(Error::OverBudget(_))
+        ));
+    }
+
+    #[test]
+    fn refuses_bad_arguments() {
+        assert!(matches!(execute(&[]), Err(Error::Usage(_))));
+        assert!(matches!(
+            execute(&strings(&["list", "--jsn"])),
+            Err(Error::Usage(_))
+        ));
+        assert!(matches!(
+            execute(&strings(&["stats", "--json", "extra"])),
+            Err(Error::Usage(_))
+        ));
+
+        assert!(!parse_json(&[]).unwrap());
+        assert!(parse_json(&strings(&["--json"])).unwrap());
+    }
+
+    #[test]
+    fn parses_minutes() {
+        assert_eq!(parse_minutes("90").unwrap(), Some(90));
+        assert_eq!(parse_minutes("none").unwrap(), None);
+        assert!(parse_minutes("-5").is_err());
+    }
+}
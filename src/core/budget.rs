@@ -0,0 +1,430 @@
+//! Playtime budgets: daily and weekly limits for each game and for all games
+//! together, counted from the tracker's history. Days start at local
+//! midnight and weeks on Monday. Everything takes the time as an argument,
+//! so the tracker's clock decides what "today" is.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use chrono::{Datelike, Local, TimeZone};
+use uuid::Uuid;
+
+use crate::core::format::format_time;
+use crate::core::library::Library;
+use crate::core::session::{day_start, History};
+use crate::core::settings::Settings;
+use crate::core::{Error, Result};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Budget {
+    /// Minutes a day, no limit when `None`.
+    pub daily_minutes: Option<u64>,
+    /// Minutes a week, no limit when `None`.
+    pub weekly_minutes: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+}
+
+impl Period {
+    /// Unix timestamp of the start of the day or week holding `now`.
+    pub fn start(self, now: u64) -> u64 {
+        let today = Local
+            .timestamp_opt(now as i64, 0)
+            .single()
+            .map(|time| time.date_naive())
+            .unwrap_or_default();
+
+        match self {
+            Period::Day => day_start(today),
+            Period::Week => {
+                day_start(today - chrono::Days::new(today.weekday().num_days_from_monday().into()))
+            }
+        }
+    }
+}
+
+/// One budget that applies to a game, with how much of it is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limit {
+    /// The budget for all games rather than the game's own.
+    pub global: bool,
+    pub period: Period,
+    pub seconds: u64,
+    pub used: u64,
+}
+
+impl Limit {
+    pub fn left(&self) -> u64 {
+        self.seconds.saturating_sub(self.used)
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let period = match self.period {
+            Period::Day => "daily",
+            Period::Week => "weekly",
+        };
+
+        let scope = match self.global {
+            true => " for all games",
+            false => "",
+        };
+
+        write!(
+            f,
+            "{} budget{} of {}",
+            period,
+            scope,
+            format_time(&self.seconds)
+        )
+    }
+}
+
+/// Something the tracker thread reports about a running game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Alert {
+    /// A warning threshold was crossed.
+    Warning {
+        id: Uuid,
+        game: String,
+        limit: Limit,
+    },
+    /// The budget ran out.
+    Exhausted {
+        id: Uuid,
+        game: String,
+        limit: Limit,
+    },
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alert::Warning { game, limit, .. } => {
+                write!(
+                    f,
+                    "{}: {} left of the {}",
+                    game,
+                    format_time(&limit.left()),
+                    limit
+                )
+            }
+            Alert::Exhausted { game, limit, .. } => write!(f, "{}: the {} is used up", game, limit),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct GameBudget {
+    id: Uuid,
+    name: String,
+    key: String,
+    budget: Budget,
+}
+
+/// The budgets from the settings and the library, ready to check.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Budgets {
+    global: Budget,
+    games: Vec<GameBudget>,
+    /// Seconds left to warn at, largest first.
+    warnings: Vec<u64>,
+}
+
+impl Budgets {
+    pub fn new(settings: &Settings, library: &Library) -> Self {
+        let mut warnings: Vec<u64> = settings
+            .budget_warnings
+            .iter()
+            .filter(|minutes| **minutes > 0)
+            .map(|minutes| minutes * 60)
+            .collect();
+
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+
+        Self {
+            global: settings.budget,
+            games: library
+                .games()
+                .iter()
+                .map(|game| GameBudget {
+                    id: game.id,
+                    name: game.name.clone(),
+                    key: game.key(),
+                    budget: game.budget,
+                })
+                .collect(),
+            warnings,
+        }
+    }
+
+    /// The budgets that apply to the game with id `id` at `now`, the one with
+    /// the least left first. Playtime is kept per executable, so the budgets
+    /// of every game sharing its executable apply too, and a second entry
+    /// without one can't get around them. The global budget counts every
+    /// game in the library.
+    pub fn limits(&self, history: &History, id: Uuid, now: u64) -> Vec<Limit> {
+        let Some(game) = self.games.iter().find(|game| game.id == id) else {
+            return Vec::new();
+        };
+
+        // games sharing an executable are only counted once
+        let keys: HashSet<&str> = self.games.iter().map(|game| game.key.as_str()).collect();
+
+        let mut limits = Vec::new();
+
+        let budgets = self
+            .games
+            .iter()
+            .filter(|other| other.key == game.key)
+            .map(|other| (other.budget, false))
+            .chain([(self.global, true)]);
+
+        for (budget, global) in budgets {
+            for (minutes, period) in [
+                (budget.daily_minutes, Period::Day),
+                (budget.weekly_minutes, Period::Week),
+            ] {
+                let Some(minutes) = minutes else {
+                    continue;
+                };
+
+                let start = period.start(now);
+
+                let used = match global {
+                    true => keys
+                        .iter()
+                        .map(|key| history.between(key, start, u64::MAX))
+                        .sum(),
+                    false => history.between(&game.key, start, u64::MAX),
+                };
+
+                let limit = Limit {
+                    global,
+                    period,
+                    seconds: minutes * 60,
+                    used,
+                };
+
+                if !limits.contains(&limit) {
+                    limits.push(limit);
+                }
+            }
+        }
+
+        limits.sort_by_key(Limit::left);
+
+        limits
+    }
+
+    /// Refuses to launch the game with id `id` once one of its budgets is
+    /// used up.
+    pub fn allow(&self, history: &History, id: Uuid, now: u64) -> Result<()> {
+        match self
+            .limits(history, id, now)
+            .into_iter()
+            .find(|limit| limit.left() == 0)
+        {
+            Some(limit) => Err(Error::OverBudget(limit.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Alerts for running games that crossed a warning threshold or ran out
+    /// since the last check. `alerted` remembers what was already reported
+    /// between calls; a game that stops running can be warned again.
+    pub fn check(
+        &self,
+        history: &History,
+        now: u64,
+        alerted: &mut HashSet<(Uuid, u64)>,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let mut checked = HashSet::new();
+
+        for game in &self.games {
+            // games sharing an executable share their limits, alert once
+            if !checked.insert(&game.key) {
+                continue;
+            }
+
+            let limit = match history.is_running(&game.key) {
+                true => self.limits(history, game.id, now).into_iter().next(),
+                false => None,
+            };
+
+            let Some(limit) = limit else {
+                alerted.retain(|(id, _)| *id != game.id);
+                continue;
+            };
+
+            // 0 stands for running out
+            let mut crossed = None;
+
+            for threshold in self.warnings.iter().copied().chain([0]) {
+                if limit.left() > threshold {
+                    alerted.remove(&(game.id, threshold));
+                } else if alerted.insert((game.id, threshold)) {
+                    crossed = Some(threshold);
+                }
+            }
+
+            // several at once, like a game started with little left, only
+            // report the last
+            match crossed {
+                Some(0) => alerts.push(Alert::Exhausted {
+                    id: game.id,
+                    game: game.name.clone(),
+                    limit,
+                }),
+                Some(_) => alerts.push(Alert::Warning {
+                    id: game.id,
+                    game: game.name.clone(),
+                    limit,
+                }),
+                None => {}
+            }
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::game;
+    use crate::core::target::LaunchTarget;
+    use crate::core::Game;
+
+    /// Monday 2024-01-01 at noon, local time.
+    fn monday() -> u64 {
+        day_start(chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()) + 12 * 60 * 60
+    }
+
+    /// The same Steam game under `name`, with a daily budget.
+    fn limited(name: &str, daily_minutes: u64) -> Game {
+        Game {
+            budget: Budget {
+                daily_minutes: Some(daily_minutes),
+                weekly_minutes: None,
+            },
+            ..game(name, LaunchTarget::Steam(504230))
+        }
+    }
+
+    fn played(start: u64, seconds: u64) -> History {
+        let mut history = History::default();
+
+        history.record_for_test("steam://rungameid/504230", start, seconds);
+
+        history
+    }
+
+    #[test]
+    fn duplicate_entries_share_a_budget() {
+        let mut library = Library::default();
+
+        library.add(limited("Limited", 60)).unwrap();
+        library
+            .add(game("Copy", LaunchTarget::Steam(504230)))
+            .unwrap();
+
+        let copy = library.get("Copy").unwrap().id;
+        let budgets = Budgets::new(&Settings::default(), &library);
+        let history = played(monday(), 60 * 60);
+
+        assert!(matches!(
+            budgets.allow(&history, copy, monday() + 60 * 60),
+            Err(Error::OverBudget(_))
+        ));
+    }
+
+    #[test]
+    fn budgets_roll_over() {
+        let mut library = Library::default();
+        let mut weekly = game("Weekly", LaunchTarget::Steam(504230));
+
+        weekly.budget.weekly_minutes = Some(60);
+        library.add(weekly).unwrap();
+
+        let id = library.get("Weekly").unwrap().id;
+        let budgets = Budgets::new(&Settings::default(), &library);
+        let history = played(monday(), 60 * 60);
+        let day = 24 * 60 * 60;
+
+        // the rest of the week, up to Sunday night
+        for now in [monday() + 2 * 60 * 60, monday() + 6 * day + 11 * 60 * 60] {
+            assert!(budgets.allow(&history, id, now).is_err());
+        }
+
+        assert!(budgets.allow(&history, id, monday() + 7 * day).is_ok());
+
+        let mut library = Library::default();
+
+        library.add(limited("Daily", 60)).unwrap();
+
+        let id = library.get("Daily").unwrap().id;
+        let budgets = Budgets::new(&Settings::default(), &library);
+
+        assert!(budgets.allow(&history, id, monday() + 2 * 60 * 60).is_err());
+        assert!(budgets.allow(&history, id, monday() + day).is_ok());
+    }
+
+    #[test]
+    fn warns_once_per_threshold() {
+        let mut library = Library::default();
+
+        library.add(limited("Limited", 30)).unwrap();
+
+        let budgets = Budgets::new(&Settings::default(), &library);
+        let mut history = History::default();
+        let mut alerted = HashSet::new();
+        let mut now = monday();
+
+        let mut play = |history: &mut History, seconds: u64, running: bool| {
+            now += seconds;
+
+            let tick = crate::core::clock::Tick {
+                now,
+                credit: seconds,
+                gap: false,
+            };
+            let seen = match running {
+                true => vec![(1, "steam://rungameid/504230".to_string())],
+                false => Vec::new(),
+            };
+
+            history.observe(tick, seen);
+
+            budgets
+                .check(history, now, &mut alerted)
+                .into_iter()
+                .map(|alert| match alert {
+                    Alert::Warning { limit, .. } => limit.left().div_ceil(60),
+                    Alert::Exhausted { .. } => 0,
+                })
+                .collect::<Vec<u64>>()
+        };
+
+        assert!(play(&mut history, 0, true).is_empty());
+        assert!(play(&mut history, 19 * 60, true).is_empty());
+        assert_eq!(play(&mut history, 90, true), [10]);
+        assert!(play(&mut history, 60, true).is_empty());
+        assert_eq!(play(&mut history, 8 * 60, true), [1]);
+        assert_eq!(play(&mut history, 60, true), [0]);
+        assert!(play(&mut history, 60, true).is_empty());
+
+        // started again after stopping, it's told again
+        assert!(play(&mut history, 60, false).is_empty());
+        assert_eq!(play(&mut history, 60, true), [0]);
+        assert!(play(&mut history, 60, true).is_empty());
+    }
+}
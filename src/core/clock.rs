@@ -0,0 +1,224 @@
+use std::time::{Duration, Instant};
+
+/// Time source for the tracker, so the accounting can be driven by a fake
+/// clock instead of the real one.
+pub trait Clock: Send {
+    /// Time since an arbitrary fixed point. Must never go backwards.
+    fn monotonic(&self) -> Duration;
+
+    /// Unix timestamp in seconds.
+    fn wall(&self) -> u64;
+}
+
+pub struct SystemClock {
+    origin: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn monotonic(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    fn wall(&self) -> u64 {
+        crate::core::session::now()
+    }
+}
+
+/// How much time one tracker pass should credit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tick {
+    /// Wall clock time of this pass.
+    pub now: u64,
+    /// Whole seconds to add to every running session.
+    pub credit: u64,
+    /// The machine was suspended, the tracker stalled or the wall clock
+    /// jumped since the last pass, so running sessions should be split.
+    pub gap: bool,
+}
+
+/// Turns clock readings taken on every tracker pass into credited seconds.
+///
+/// Time is measured with the monotonic clock rather than assumed from the
+/// sleep interval, so slow scans and lock waits are counted. A pass that
+/// arrives much later than expected, or whose wall clock moved much further
+/// than the monotonic one (it stops while suspended on Linux), is treated
+/// as a gap and credited at most one interval.
+pub struct Ticker<C: Clock> {
+    clock: C,
+    interval: Duration,
+    /// Interval at the last pass, which the sleep before the next one used.
+    slept: Duration,
+    last: Option<(Duration, u64)>,
+    carry: Duration,
+}
+
+impl<C: Clock> Ticker<C> {
+    pub fn new(clock: C, interval: Duration) -> Self {
+        Self {
+            clock,
+            interval,
+            slept: interval,
+            last: None,
+            carry: Duration::ZERO,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn tick(&mut self) -> Tick {
+        let mono = self.clock.monotonic();
+        let now = self.clock.wall();
+
+        // lowering the interval doesn't cut short a sleep already underway
+        let interval = self.interval.max(self.slept);
+
+        self.slept = self.interval;
+
+        let Some((last_mono, last_wall)) = self.last.replace((mono, now)) else {
+            return Tick {
+                now,
+                credit: 0,
+                gap: false,
+            };
+        };
+
+        let elapsed = mono.saturating_sub(last_mono);
+        let wall_elapsed = Duration::from_secs(now.saturating_sub(last_wall));
+
+        // allow for sleep overshoot and a slow scan before calling it a gap
+        let slack = interval * 2;
+
+        let gap = elapsed > slack || wall_elapsed > elapsed + slack || now < last_wall;
+
+        let elapsed = if gap {
+            self.carry = Duration::ZERO;
+
+            elapsed.min(interval)
+        } else {
+            elapsed + self.carry
+        };
+
+        self.carry = Duration::from_nanos(elapsed.subsec_nanos().into());
+
+        Tick {
+            now,
+            credit: elapsed.as_secs(),
+            gap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeClock {
+        mono: Duration,
+        wall: u64,
+    }
+
+    impl Clock for FakeClock {
+        fn monotonic(&self) -> Duration {
+            self.mono
+        }
+
+        fn wall(&self) -> u64 {
+            self.wall
+        }
+    }
+
+    fn ticker(interval: u64) -> Ticker<FakeClock> {
+        let clock = FakeClock {
+            mono: Duration::ZERO,
+            wall: 1_000_000,
+        };
+        let mut ticker = Ticker::new(clock, Duration::from_secs(interval));
+
+        assert_eq!(ticker.tick().credit, 0);
+
+        ticker
+    }
+
+    fn advance(ticker: &mut Ticker<FakeClock>, mono: Duration, wall: u64) -> Tick {
+        ticker.clock.mono += mono;
+        ticker.clock.wall += wall;
+
+        ticker.tick()
+    }
+
+    #[test]
+    fn credits_elapsed_time() {
+        let mut ticker = ticker(5);
+
+        let tick = advance(&mut ticker, Duration::from_secs(6), 6);
+
+        assert_eq!((tick.credit, tick.gap), (6, false));
+        assert_eq!(tick.now, 1_000_006);
+    }
+
+    #[test]
+    fn carries_fractions_over() {
+        let mut ticker = ticker(5);
+
+        let half = Duration::from_millis(2500);
+
+        assert_eq!(advance(&mut ticker, half, 2).credit, 2);
+        assert_eq!(advance(&mut ticker, half, 3).credit, 3);
+    }
+
+    #[test]
+    fn late_passes_are_gaps() {
+        let mut ticker = ticker(5);
+
+        let tick = advance(&mut ticker, Duration::from_millis(60_500), 60);
+
+        assert_eq!((tick.credit, tick.gap), (5, true));
+
+        // the fraction isn't carried past a gap
+        assert_eq!(advance(&mut ticker, Duration::from_secs(5), 5).credit, 5);
+    }
+
+    #[test]
+    fn wall_clock_jumps_are_gaps() {
+        let mut ticker = ticker(5);
+
+        // suspended, only the wall clock moved
+        let tick = advance(&mut ticker, Duration::from_secs(5), 3600);
+
+        assert_eq!((tick.credit, tick.gap), (5, true));
+
+        // set back
+        ticker.clock.wall -= 600;
+
+        let tick = advance(&mut ticker, Duration::from_secs(5), 5);
+
+        assert_eq!((tick.credit, tick.gap), (5, true));
+    }
+
+    #[test]
+    fn lowering_the_interval_is_not_a_gap() {
+        let mut ticker = ticker(30);
+
+        // changed while the tracker was sleeping for the old interval
+        ticker.set_interval(Duration::from_secs(1));
+
+        let tick = advance(&mut ticker, Duration::from_secs(30), 30);
+
+        assert_eq!((tick.credit, tick.gap), (30, false));
+
+        // the next sleep uses the new interval
+        let tick = advance(&mut ticker, Duration::from_secs(30), 30);
+
+        assert_eq!((tick.credit, tick.gap), (1, true));
+    }
+}
@@ -0,0 +1,248 @@
+//! Lets other processes drive the running launcher, so panic can be bound
+//! to a desktop hotkey while a fullscreen game has focus.
+//!
+//! The launcher listens on a Unix socket for one command per line, answering
+//! with one line, and treats SIGUSR1 like a `panic` command:
+//!
+//! ```sh
+//! echo panic | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/gamelunch.sock
+//! pkill -USR1 gamelunch
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::time::Duration;
+
+use crate::core::{Error, Result};
+
+/// What can be asked of the running launcher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Panic,
+    Resume,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Panic => write!(f, "panic"),
+            Command::Resume => write!(f, "resume"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "panic" => Ok(Command::Panic),
+            "resume" => Ok(Command::Resume),
+            other => Err(Error::Usage(other.to_string())),
+        }
+    }
+}
+
+/// Where the control socket lives: the runtime directory if there is one,
+/// otherwise a private folder next to the library.
+pub fn socket_path() -> Option<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Some(dir.join(format!("{}.sock", crate::APP_ID))),
+        None => {
+            dirs::data_dir().map(|dir| dir.join(crate::APP_ID).join("control").join("control.sock"))
+        }
+    }
+}
+
+/// How long a client gets to send its command before it is hung up on.
+#[cfg(unix)]
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers a command, returning the line to send back.
+#[cfg(unix)]
+type Handler = Arc<dyn Fn(Command) -> String + Send + Sync>;
+
+/// Answers SIGUSR1 and commands on the socket at `path` with `handler`, each
+/// on its own thread. The signal is answered even when the socket fails,
+/// like when another launcher is already listening.
+#[cfg(unix)]
+pub fn listen(
+    path: Option<&Path>,
+    handler: impl Fn(Command) -> String + Send + Sync + 'static,
+) -> Result<()> {
+    let handler: Handler = Arc::new(handler);
+
+    on_signal(handler.clone())?;
+
+    serve(path, handler)
+}
+
+/// Treats SIGUSR1 like a `panic` command from now on. The handler is
+/// process-wide and stays installed until exit.
+#[cfg(unix)]
+fn on_signal(handler: Handler) -> Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGUSR1])?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            log::info!("Received SIGUSR1");
+
+            handler(Command::Panic);
+        }
+    });
+
+    Ok(())
+}
+
+/// Binds the socket at `path` and answers its commands on a thread of
+/// their own.
+#[cfg(unix)]
+fn serve(path: Option<&Path>, handler: Handler) -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let Some(path) = path else {
+        return Err(Error::Control("could not find a socket path".to_string()));
+    };
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::Control(format!("{} is in use", path.display())));
+        }
+
+        // left behind by a launcher that didn't exit cleanly
+        std::fs::remove_file(path)?;
+    }
+
+    // private, so nobody can connect before the socket's own permissions
+    // are set
+    if let Some(parent) = path.parent().filter(|parent| !parent.exists()) {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+
+            let handler = handler.clone();
+
+            // each on its own thread, otherwise a client that sends nothing
+            // would hold up the next panic
+            std::thread::spawn(move || {
+                let mut line = String::new();
+
+                let read = stream
+                    .set_read_timeout(Some(READ_TIMEOUT))
+                    .and_then(|()| BufReader::new(&stream).read_line(&mut line));
+
+                if read.is_err() {
+                    return;
+                }
+
+                let reply = match line.parse() {
+                    Ok(command) => {
+                        log::info!("Received {} on the control socket", command);
+
+                        handler(command)
+                    }
+                    Err(err) => err.to_string(),
+                };
+
+                let _ = writeln!(stream, "{}", reply);
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn listen(
+    _path: Option<&Path>,
+    _handler: impl Fn(Command) -> String + Send + Sync + 'static,
+) -> Result<()> {
+    Err(Error::Control("not supported on this platform".to_string()))
+}
+
+/// Sends `command` to the launcher listening at `path` and returns its
+/// answer, which is empty if the launcher exited while handling it.
+#[cfg(unix)]
+pub fn send(path: &Path, command: Command) -> Result<String> {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)?;
+
+    writeln!(stream, "{}", command)?;
+
+    let mut reply = String::new();
+
+    stream.read_to_string(&mut reply)?;
+
+    Ok(reply.trim().to_string())
+}
+
+#[cfg(not(unix))]
+pub fn send(_path: &Path, _command: Command) -> Result<String> {
+    Err(Error::Control("not supported on this platform".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::temp_dir;
+
+    #[test]
+    fn parses_commands() {
+        for command in [Command::Panic, Command::Resume] {
+            assert_eq!(command.to_string().parse::<Command>().unwrap(), command);
+        }
+
+        assert_eq!(" panic\n".parse::<Command>().unwrap(), Command::Panic);
+        assert!(matches!("quit".parse::<Command>(), Err(Error::Usage(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn answers_on_the_socket() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixStream;
+
+        let dir = temp_dir("control");
+        let path = dir.join("private").join("control.sock");
+
+        // `serve` alone, `listen` would also take over SIGUSR1 for the whole
+        // test binary
+        serve(Some(&path), Arc::new(|command| format!("did {}", command))).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        assert_eq!(mode(&path), 0o600);
+
+        // a client that never sends anything doesn't hold up the others
+        let _idle = UnixStream::connect(&path).unwrap();
+
+        assert_eq!(send(&path, Command::Resume).unwrap(), "did resume");
+        assert_eq!(send(&path, Command::Panic).unwrap(), "did panic");
+
+        // a second launcher is turned away
+        assert!(serve(Some(&path), Arc::new(|_| String::new())).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,647 @@
+//! The on-disk library file.
+//!
+//! Games and playtime live in `library.json` under the platform data
+//! directory (`$XDG_DATA_HOME/gamelunch` on Linux), separate from the window
+//! state eframe persists. Another folder can be chosen with `move_to`. The
+//! file looks like:
+//!
+//! ```json
+//! {
+//!   "version": 2,
+//!   "games": [{
+//!     "id": "0b5e3a9c-6f1d-4c8e-9a57-2d4f8e6b1c30", "name": "Celeste", "author": "Maddy Makes Games", "location": { "kind": "executable", "value": "/games/Celeste" }, "rules": [],
+//!     "args": ["-windowed"], "env": { "SDL_VIDEODRIVER": "x11", "LD_PRELOAD": null }, "working_dir": null,
+//!     "wrappers": ["mangohud"], "wine": null, "budget": { "daily_minutes": 60, "weekly_minutes": null }
+//!   }],
+//!   "sessions": [{ "process": "/games/Celeste", "pid": 4242, "start": 1726000000, "end": 1726003600, "seconds": 3600 }],
+//!   "imported_time": { "/games/Celeste": 3600 },
+//!   "settings": { "tracking_scope": "all", "allowlist": [], "use_denylist": true },
+//!   "restore": { "time": 1726003600, "games": [{ "id": "0b5e3a9c-6f1d-4c8e-9a57-2d4f8e6b1c30", "name": "Celeste", "args": [] }] },
+//!   "trash": []
+//! }
+//! ```
+//!
+//! `id` is what the launcher refers to a game by, and stays the same when it
+//! is renamed or edited.
+//! `location` is what launching starts, see `LaunchTarget`: an `executable`
+//! path, a `shell` command, a `url`, a `flatpak` app id, a `steam` app id or
+//! an `emulator` with `{ "emulator": path, "rom": path }`.
+//! `rules` are extra ways to recognize a game's processes, like
+//! `{ "kind": "command_line", "value": "Celeste\\.exe" }`, see `MatchRule`.
+//! `env` sets variables, or removes those that are `null`. A `null`
+//! `working_dir` starts the game in the folder of its executable.
+//! `wrappers` names profiles from `settings.wrappers` the game is run
+//! through, see `Wrapper`. `wine` runs a Windows game through a runner from
+//! `settings.runners`, like `{ "runner": "GE-Proton9", "prefix": null,
+//! "env": { "DXVK_HUD": "fps" } }`, see `WineConfig`. `budget` limits the
+//! minutes the game is played a day or a week, on top of `settings.budget`
+//! for all games, see `Budget`.
+//! `sessions` holds every run the tracker saw, with unix timestamps and the
+//! seconds credited to it; runs of games started from the launcher also get
+//! `exit_status` and, if a signal killed them, `exit_signal`. Processes are
+//! keyed by canonical executable path, or by lowercase name when the
+//! executable couldn't be read.
+//! `imported_time` is playtime recorded before sessions existed (the old
+//! eframe state stored only a `time` total per process). `settings` holds preferences;
+//! any missing setting takes its default. `settings.lock` keeps the argon2
+//! hash of the PIN or passphrase, see `Lock`. `restore` lists the games that
+//! were running when panic last killed them, or is `null`. `trash` holds
+//! removed games for `TRASH_DAYS`, each as `{ "game": .., "removed":
+//! timestamp, "index": .., "sessions": [..], "imported_time": seconds }` with
+//! the playtime taken out of `sessions` and `imported_time`.
+//!
+//! `version` is bumped whenever the layout changes, and `MIGRATIONS` upgrades
+//! older files on load. Files written by a newer launcher are refused rather
+//! than loaded with missing fields.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::core::library::Library;
+use crate::core::lock;
+use crate::core::restore::Snapshot;
+use crate::core::session::{History, Session};
+use crate::core::settings::Settings;
+use crate::core::store::Store;
+use crate::core::tracker::process_key;
+use crate::core::{Error, Result};
+
+pub const VERSION: u64 = 2;
+
+/// `MIGRATIONS[n]` upgrades a version `n` file to version `n + 1`. Version 0
+/// is the layout of the old eframe-persisted `GameLunch` struct.
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v0, migrate_v1];
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub struct Data {
+    pub version: u64,
+    #[serde(flatten)]
+    pub games: Library,
+    pub sessions: Vec<Session>,
+    pub imported_time: HashMap<String, u64>,
+    pub settings: Settings,
+    pub restore: Option<Snapshot>,
+}
+
+impl Default for Data {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            games: Library::default(),
+            sessions: Vec::new(),
+            imported_time: HashMap::new(),
+            settings: Settings::default(),
+            restore: None,
+        }
+    }
+}
+
+/// The games and playtime fields of the `GameLunch` struct that used to be
+/// stored under `eframe::APP_KEY`.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+#[serde(default)]
+pub struct LegacyData {
+    games: Vec<LegacyGame>,
+    time: HashMap<String, u64>,
+}
+
+/// A game as it was saved back then, with a plain path as its location so
+/// the migrations see the version 0 layout.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+struct LegacyGame {
+    name: String,
+    author: String,
+    location: PathBuf,
+}
+
+/// The library of older versions, which kept it inside the eframe state.
+pub fn legacy() -> Option<LegacyData> {
+    let store = Store::open(eframe::storage_dir(crate::APP_ID)?.join("app.ron")).ok()?;
+
+    store.get(eframe::APP_KEY)
+}
+
+impl Data {
+    /// Loads the library at `path`. When it does not exist yet, it is created
+    /// from `legacy` if that finds data from an older launcher.
+    pub fn open(path: &Path, legacy: impl FnOnce() -> Option<LegacyData>) -> Result<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        match legacy() {
+            Some(legacy) => {
+                log::info!("Migrating saved games to {}", path.display());
+
+                let data = Self::from_value(serde_json::to_value(legacy).map_err(storage)?)?;
+
+                data.save(path)?;
+
+                Ok(data)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn new(
+        games: Library,
+        history: &History,
+        settings: Settings,
+        restore: Option<Snapshot>,
+    ) -> Self {
+        Self {
+            games,
+            sessions: history.sessions().cloned().collect(),
+            imported_time: history.imported().clone(),
+            settings,
+            restore,
+            ..Default::default()
+        }
+    }
+
+    pub fn into_parts(self) -> (Library, History, Settings) {
+        (
+            self.games,
+            History::new(self.sessions, self.imported_time),
+            self.settings,
+        )
+    }
+
+    /// The recorded playtime, to change and put back with `set_history`.
+    pub fn history(&self) -> History {
+        History::new(self.sessions.clone(), self.imported_time.clone())
+    }
+
+    pub fn set_history(&mut self, history: &History) {
+        self.sessions = history.sessions().cloned().collect();
+        self.imported_time = history.imported().clone();
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+
+        let mut data = Self::from_value(serde_json::from_str(&text).map_err(storage)?)?;
+
+        data.games.empty_trash(Some(crate::core::session::now()));
+
+        Ok(data)
+    }
+
+    fn from_value(mut value: Value) -> Result<Self> {
+        if !value.is_object() {
+            return Err(Error::Storage("expected a JSON object".to_string()));
+        }
+
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+        if version > VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&mut value);
+        }
+
+        value["version"] = VERSION.into();
+
+        serde_json::from_value(value).map_err(storage)
+    }
+
+    /// Writes the library next to `path` and renames it into place, so a
+    /// crash mid-write never leaves a truncated file behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = std::fs::File::create(&tmp)?;
+        serde_json::to_writer_pretty(&mut file, self).map_err(storage)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+/// When the file at `path` was last written, to notice other programs
+/// changing it.
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Where the library file lives.
+pub fn default_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("library.json"))
+}
+
+/// The folder holding the library and the trigger log: the one chosen with
+/// `move_to`, otherwise `default_data_dir`.
+pub fn data_dir() -> Option<PathBuf> {
+    let chosen = location_file()
+        .and_then(|file| std::fs::read_to_string(file).ok())
+        .map(|dir| dir.trim().to_string())
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+
+    chosen.or_else(default_data_dir)
+}
+
+/// `gamelunch` in the platform data directory.
+pub fn default_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(crate::APP_ID))
+}
+
+/// Remembers the chosen data directory, which can't be kept in the library
+/// it points to.
+fn location_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(crate::APP_ID).join("data_dir"))
+}
+
+/// Saves `data` in `dir`, makes that the data directory and removes the old
+/// library, taking the logs, wrong guesses and Wine prefixes along. `None`
+/// goes back to the default. Returns the new library path.
+pub fn move_to(data: &Data, dir: Option<&Path>) -> Result<PathBuf> {
+    let missing = || Error::DataDir("could not find the platform directories".to_string());
+
+    let dir = match dir {
+        Some(dir) if !dir.is_absolute() => {
+            return Err(Error::DataDir(format!(
+                "{} is not an absolute path",
+                dir.display()
+            )));
+        }
+        Some(dir) => dir.to_path_buf(),
+        None => default_data_dir().ok_or_else(missing)?,
+    };
+
+    let old = default_path().ok_or_else(missing)?;
+    let path = dir.join("library.json");
+
+    if path == old {
+        return Ok(path);
+    }
+
+    if path.exists() {
+        return Err(Error::DataDir(format!(
+            "{} already has a library",
+            dir.display()
+        )));
+    }
+
+    data.save(&path)?;
+
+    let file = location_file().ok_or_else(missing)?;
+
+    if Some(&dir) == default_data_dir().as_ref() {
+        if file.exists() {
+            std::fs::remove_file(&file)?;
+        }
+    } else {
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(&file, dir.to_string_lossy().as_bytes())?;
+    }
+
+    let mut prefixes = Ok(());
+
+    if let Some(old_dir) = old.parent() {
+        for name in ["triggers.log", lock::LOG_FILE, lock::GUESSES_FILE] {
+            let log = old_dir.join(name);
+
+            // copied, since the new folder may be on another drive
+            if log.exists() && std::fs::copy(&log, dir.join(name)).is_ok() {
+                let _ = std::fs::remove_file(&log);
+            }
+        }
+
+        prefixes = move_dir(&old_dir.join("prefixes"), &dir.join("prefixes"));
+    }
+
+    if old.exists() {
+        std::fs::remove_file(&old)?;
+    }
+
+    prefixes.map_err(|err| {
+        Error::DataDir(format!(
+            "moved the library, but the Wine prefixes stayed behind: {}",
+            err
+        ))
+    })?;
+
+    Ok(path)
+}
+
+/// Moves the folder `from` to `to`, copying it over when they are on
+/// different drives. Nothing happens if `from` is missing or `to` exists.
+fn move_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !from.is_dir() || to.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    if let Err(err) = copy_dir(from, to) {
+        let _ = std::fs::remove_dir_all(to);
+
+        return Err(err);
+    }
+
+    std::fs::remove_dir_all(from)
+}
+
+/// Copies the folder `from` to `to`, recreating symlinks instead of following
+/// them. A Wine prefix links its `z:` drive to `/`.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        let target = to.join(entry.file_name());
+
+        if kind.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+        } else if kind.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn storage(err: serde_json::Error) -> Error {
+    Error::Storage(err.to_string())
+}
+
+/// Games had a plain executable path and time was kept per lowercase file
+/// name. The time moves to the game's executable path when exactly one game
+/// has that file name.
+fn migrate_v0(value: &mut Value) {
+    let mut keys: HashMap<String, Vec<String>> = HashMap::new();
+
+    if !value["games"].is_array() {
+        value["games"] = Value::Array(Vec::new());
+    }
+
+    for game in value["games"].as_array_mut().into_iter().flatten() {
+        let location = game["location"].as_str().unwrap_or_default().to_string();
+        let path = Path::new(&location);
+
+        if let Some(name) = path.file_name() {
+            let keys = keys
+                .entry(name.to_string_lossy().to_lowercase())
+                .or_default();
+            let key = process_key(path);
+
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        game["id"] = Uuid::new_v4().to_string().into();
+        game["location"] = serde_json::json!({ "kind": "executable", "value": location });
+    }
+
+    let mut imported: HashMap<String, u64> = HashMap::new();
+
+    for (process, time) in value["time"].as_object().into_iter().flatten() {
+        let process = match keys.get(process) {
+            Some(keys) if keys.len() == 1 => keys[0].clone(),
+            _ => process.clone(),
+        };
+
+        *imported.entry(process).or_default() += time.as_u64().unwrap_or(0);
+    }
+
+    value["imported_time"] = serde_json::to_value(imported).unwrap_or_default();
+    value["sessions"] = Value::Array(Vec::new());
+    value["settings"] = Value::Object(Default::default());
+    value["restore"] = Value::Null;
+
+    if let Some(value) = value.as_object_mut() {
+        value.remove("time");
+    }
+}
+
+/// Version 1 files written before games had ids load them as nil. Games
+/// without an id, or sharing one with another game, get a fresh one, and the
+/// restore snapshot follows them by name.
+fn migrate_v1(value: &mut Value) {
+    let mut seen = HashSet::new();
+    let mut names = HashMap::new();
+
+    let mut games: Vec<&mut Value> = Vec::new();
+
+    if let Some(value) = value.as_object_mut() {
+        for (field, list) in value.iter_mut() {
+            match field.as_str() {
+                "games" => games.extend(list.as_array_mut().into_iter().flatten()),
+                "trash" => games.extend(
+                    list.as_array_mut()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|trashed| trashed.get_mut("game")),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    for game in games.into_iter().filter(|game| game.is_object()) {
+        let id = game["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .filter(|id| !id.is_nil() && seen.insert(*id));
+
+        if id.is_none() {
+            let id = Uuid::new_v4();
+
+            seen.insert(id);
+            game["id"] = id.to_string().into();
+
+            if let Some(name) = game["name"].as_str() {
+                names.entry(name.to_string()).or_insert(id);
+            }
+        }
+    }
+
+    let restored = value
+        .get_mut("restore")
+        .and_then(|restore| restore.get_mut("games"))
+        .and_then(Value::as_array_mut);
+
+    for running in restored
+        .into_iter()
+        .flatten()
+        .filter(|game| game.is_object())
+    {
+        let known = running["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .is_some_and(|id| !id.is_nil() && seen.contains(&id));
+
+        if !known {
+            if let Some(id) = running["name"].as_str().and_then(|name| names.get(name)) {
+                running["id"] = id.to_string().into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::temp_dir;
+    use crate::core::target::LaunchTarget;
+
+    #[test]
+    fn legacy_time_moves_to_the_game() {
+        let legacy = LegacyData {
+            games: vec![LegacyGame {
+                name: "Celeste".to_string(),
+                author: "Maddy Makes Games".to_string(),
+                location: "/games/Celeste".into(),
+            }],
+            time: HashMap::from([("celeste".to_string(), 3600)]),
+        };
+
+        let data = Data::from_value(serde_json::to_value(legacy).unwrap()).unwrap();
+        let (library, history, _) = data.into_parts();
+        let game = library.get("Celeste").unwrap();
+
+        assert_eq!(
+            game.location,
+            LaunchTarget::Executable("/games/Celeste".into())
+        );
+        assert_eq!(history.total(&game.key()), 3600);
+        assert_eq!(history.total("celeste"), 0);
+    }
+
+    #[test]
+    fn migrates_the_eframe_layout() {
+        let v0 = serde_json::json!({
+            "games": [{ "name": "Celeste", "author": "Maddy Makes Games", "location": "/games/Celeste" }],
+            "time": { "celeste": 3600, "firefox": 60 },
+        });
+
+        let data = Data::from_value(v0).unwrap();
+
+        assert_eq!(data.version, VERSION);
+        assert!(data.sessions.is_empty());
+        assert!(data.restore.is_none());
+        assert!(data.games.trash().is_empty());
+        assert_eq!(data.settings, Settings::default());
+
+        let game = data.games.get("Celeste").unwrap();
+
+        assert!(!game.id.is_nil());
+        assert!(game.rules.is_empty() && game.args.is_empty() && game.wrappers.is_empty());
+        assert!(game.wine.is_none());
+        assert_eq!(game.budget, Default::default());
+        assert_eq!(data.imported_time.get("/games/Celeste"), Some(&3600));
+        assert_eq!(data.imported_time.get("firefox"), Some(&60));
+    }
+
+    #[test]
+    fn gives_version_1_games_ids() {
+        let game = |name: &str| {
+            serde_json::json!({
+                "name": name,
+                "author": "Someone",
+                "location": { "kind": "steam", "value": 504230 },
+            })
+        };
+        let v1 = serde_json::json!({
+            "version": 1,
+            "games": [game("Celeste"), game("Other")],
+            "sessions": [],
+            "imported_time": {},
+            "settings": {},
+            "restore": { "time": 100, "games": [{ "name": "Celeste", "location": game("Celeste")["location"], "args": [] }] },
+        });
+
+        let dir = temp_dir("v1");
+        let path = dir.join("library.json");
+        std::fs::write(&path, v1.to_string()).unwrap();
+
+        let data = Data::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let ids: Vec<Uuid> = data.games.games().iter().map(|game| game.id).collect();
+
+        assert_eq!(data.version, VERSION);
+        assert!(ids.iter().all(|id| !id.is_nil()));
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(data.games.find(ids[1]).unwrap().name, "Other");
+        assert_eq!(data.restore.unwrap().games[0].id, ids[0]);
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let data = Data::from_value(serde_json::json!({})).unwrap();
+        let saved = serde_json::to_value(&data).unwrap();
+
+        assert_eq!(saved["version"], VERSION);
+        assert!(Data::from_value(saved).is_ok());
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let value = serde_json::json!({ "version": VERSION + 1 });
+
+        assert!(matches!(
+            Data::from_value(value),
+            Err(Error::UnsupportedVersion(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_prefixes_without_following_links() {
+        let dir = temp_dir("copy");
+        let from = dir.join("from");
+        let to = dir.join("to");
+
+        std::fs::create_dir_all(from.join("drive_c")).unwrap();
+        std::fs::create_dir_all(from.join("dosdevices")).unwrap();
+        std::fs::write(from.join("drive_c/save.dat"), "progress").unwrap();
+        std::os::unix::fs::symlink("/", from.join("dosdevices/z:")).unwrap();
+
+        copy_dir(&from, &to).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(to.join("drive_c/save.dat")).unwrap(),
+            "progress"
+        );
+        assert_eq!(
+            std::fs::read_link(to.join("dosdevices/z:")).unwrap(),
+            Path::new("/")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+//! Works out what kind of file a game executable is and how to start it,
+//! from its first bytes rather than trusting the extension or exec bit.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use crate::core::{Error, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Elf,
+    AppImage,
+    /// A PE file, like a `.exe`.
+    Windows,
+    /// With the words of its `#!` line, if it has one.
+    Script(Option<Vec<String>>),
+    Jar,
+    Unknown,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileType::Elf => write!(f, "Linux program"),
+            FileType::AppImage => write!(f, "AppImage"),
+            FileType::Windows => write!(f, "Windows program"),
+            FileType::Script(_) => write!(f, "script"),
+            FileType::Jar => write!(f, "Java archive"),
+            FileType::Unknown => write!(f, "unknown file"),
+        }
+    }
+}
+
+/// How a file is started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    Direct,
+    /// Set the exec bit first, for downloads that lost it.
+    ChmodAndRun,
+    /// Pass the file to these words, like `sh` or `/usr/bin/env python3`.
+    Interpreter(Vec<String>),
+    Java,
+    /// Through the game's Wine or Proton runner.
+    Wine,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Direct => write!(f, "run directly"),
+            Method::ChmodAndRun => write!(f, "made executable and run"),
+            Method::Interpreter(words) => write!(f, "run with {}", words.join(" ")),
+            Method::Java => write!(f, "run with java -jar"),
+            Method::Wine => write!(f, "run with Wine or Proton"),
+        }
+    }
+}
+
+/// What the launcher makes of an executable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    pub file_type: FileType,
+    pub method: Method,
+}
+
+impl Plan {
+    /// Sniffs `path` and picks how to run it. `runner` says whether the game
+    /// has a Wine or Proton runner, which only Windows programs are run
+    /// through. Fails with a specific error when something needed is missing
+    /// or a native file has a runner.
+    pub fn new(path: &Path, runner: bool) -> Result<Self> {
+        if path.is_dir() {
+            return Err(Error::NotExecutable(path.to_path_buf()));
+        }
+
+        let file_type = detect(path)?;
+        let executable = is_executable(path);
+
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let method = match &file_type {
+            FileType::Windows if runner => Method::Wine,
+            FileType::Windows if cfg!(windows) => Method::Direct,
+            FileType::Windows => return Err(Error::NeedsRunner(path.to_path_buf())),
+            // Wine can't tell what to do with these any better
+            FileType::Unknown if runner => Method::Wine,
+            _ if runner => {
+                return Err(Error::RunsNatively(
+                    path.to_path_buf(),
+                    file_type.to_string(),
+                ))
+            }
+            FileType::Elf | FileType::AppImage | FileType::Unknown if executable => Method::Direct,
+            FileType::Elf | FileType::AppImage => Method::ChmodAndRun,
+            FileType::Script(Some(_)) if executable => Method::Direct,
+            FileType::Script(Some(words)) => Method::Interpreter(words.clone()),
+            FileType::Script(None) => match extension.as_str() {
+                "py" => Method::Interpreter(vec!["python3".to_string()]),
+                "bash" => Method::Interpreter(vec!["bash".to_string()]),
+                _ => Method::Interpreter(vec!["sh".to_string()]),
+            },
+            FileType::Jar => Method::Java,
+            FileType::Unknown => return Err(Error::NotExecutable(path.to_path_buf())),
+        };
+
+        let needed = match &method {
+            Method::Interpreter(words) => match words.as_slice() {
+                // env looks up the real interpreter
+                [env, program, ..] if env.ends_with("/env") => Some(program.clone()),
+                [program, ..] => Some(program.clone()),
+                [] => None,
+            },
+            Method::Java => Some("java".to_string()),
+            _ => None,
+        };
+
+        if let Some(program) = needed {
+            if find_program(&program).is_none() {
+                return Err(Error::MissingInterpreter(program, path.to_path_buf()));
+            }
+        }
+
+        Ok(Self { file_type, method })
+    }
+
+    /// The words starting `path` this way. Wine games get just the path,
+    /// the runner goes in front.
+    pub fn words(&self, path: &Path) -> Vec<String> {
+        let path = path.to_string_lossy().into_owned();
+
+        match &self.method {
+            Method::Direct | Method::ChmodAndRun | Method::Wine => vec![path],
+            Method::Interpreter(words) => words.iter().cloned().chain([path]).collect(),
+            Method::Java => vec!["java".to_string(), "-jar".to_string(), path],
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.file_type, self.method)
+    }
+}
+
+/// Reads the magic bytes of `path`.
+pub fn detect(path: &Path) -> Result<FileType> {
+    let mut head = Vec::new();
+
+    std::fs::File::open(path)?
+        .take(256)
+        .read_to_end(&mut head)?;
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let file_type = if head.starts_with(b"\x7fELF") {
+        // AppImages mark themselves in the ELF padding
+        match head.get(8..11) {
+            Some(b"AI\x01") | Some(b"AI\x02") => FileType::AppImage,
+            _ => FileType::Elf,
+        }
+    } else if head.starts_with(b"MZ") {
+        FileType::Windows
+    } else if head.starts_with(b"#!") {
+        let line = head[2..]
+            .split(|byte| *byte == b'\n')
+            .next()
+            .unwrap_or_default();
+
+        let words = String::from_utf8_lossy(line)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        FileType::Script(Some(words).filter(|words| !words.is_empty()))
+    } else if head.starts_with(b"PK\x03\x04") && extension == "jar" {
+        FileType::Jar
+    } else if ["sh", "bash", "py"].contains(&extension.as_str()) {
+        FileType::Script(None)
+    } else if extension == "appimage" {
+        // type 1 AppImages are ISO images without the marker
+        FileType::AppImage
+    } else {
+        FileType::Unknown
+    };
+
+    Ok(file_type)
+}
+
+/// Like `detect`, but remembers the type of each file until it is modified,
+/// for the process matcher that is rebuilt on every settings change.
+pub fn detect_cached(path: &Path) -> Result<FileType> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, FileType)>>> = OnceLock::new();
+
+    let modified = path.metadata()?.modified()?;
+    let cache = CACHE.get_or_init(Default::default);
+
+    if let Some((time, file_type)) = cache.lock().unwrap().get(path) {
+        if *time == modified {
+            return Ok(file_type.clone());
+        }
+    }
+
+    let file_type = detect(path)?;
+
+    cache
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), (modified, file_type.clone()));
+
+    Ok(file_type)
+}
+
+/// Where `program` is: the path itself if it has a slash, otherwise the
+/// first match on `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| [dir.join(program), dir.join(format!("{}.exe", program))])
+        .find(|candidate| candidate.is_file())
+}
+
+/// Sets the exec bit of `path` for its owner, see `Method::ChmodAndRun`.
+#[cfg(unix)]
+pub fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = path.metadata()?.permissions();
+
+    permissions.set_mode(permissions.mode() | 0o100);
+
+    std::fs::set_permissions(path, permissions)?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Whether `path` runs by itself: by its execute bit on Unix, its extension
+/// elsewhere.
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        ["exe", "bat", "cmd", "com"]
+            .iter()
+            .any(|known| ext.eq_ignore_ascii_case(known))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::temp_dir;
+
+    /// Writes `bytes` to `name` in `dir`.
+    fn file(dir: &Path, name: &str, bytes: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+
+        std::fs::write(&path, bytes).unwrap();
+
+        path
+    }
+
+    fn elf(marker: &[u8]) -> Vec<u8> {
+        [b"\x7fELF\x02\x01\x01\x00".as_slice(), marker, &[0; 8]].concat()
+    }
+
+    #[test]
+    fn reads_magic_bytes() {
+        let dir = temp_dir("detect-magic");
+
+        let cases: [(&str, Vec<u8>, FileType); 8] = [
+            ("game", elf(b""), FileType::Elf),
+            ("game.AppImage", elf(b"AI\x02"), FileType::AppImage),
+            ("type1.AppImage", b"CD001".to_vec(), FileType::AppImage),
+            ("Game.exe", b"MZ\x90\x00".to_vec(), FileType::Windows),
+            ("game.jar", b"PK\x03\x04".to_vec(), FileType::Jar),
+            ("game.zip", b"PK\x03\x04".to_vec(), FileType::Unknown),
+            ("start.sh", b"cd game\n".to_vec(), FileType::Script(None)),
+            ("notes.txt", b"hello".to_vec(), FileType::Unknown),
+        ];
+
+        for (name, bytes, expected) in cases {
+            let path = file(&dir, name, &bytes);
+
+            assert_eq!(detect(&path).unwrap(), expected, "{}", name);
+        }
+
+        // the extension doesn't win over the contents
+        let path = file(&dir, "fake.exe", &elf(b"AI\x01"));
+
+        assert_eq!(detect(&path).unwrap(), FileType::AppImage);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_shebangs() {
+        let dir = temp_dir("detect-shebang");
+
+        let cases: [(&[u8], Option<&[&str]>); 3] = [
+            (b"#!/bin/sh\necho\n", Some(&["/bin/sh"])),
+            (
+                b"#! /usr/bin/env python3 -u\nprint()\n",
+                Some(&["/usr/bin/env", "python3", "-u"]),
+            ),
+            (b"#!\n", None),
+        ];
+
+        for (bytes, words) in cases {
+            let path = file(&dir, "run", bytes);
+            let words = words.map(|words| words.iter().map(|word| word.to_string()).collect());
+
+            assert_eq!(detect(&path).unwrap(), FileType::Script(words));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn picks_how_to_run() {
+        let dir = temp_dir("detect-plan");
+
+        let elf = file(&dir, "game", &elf(b""));
+        let script = file(&dir, "start", b"#!/bin/sh\n");
+        let plain = file(&dir, "start.sh", b"cd game\n");
+        let exe = file(&dir, "Game.exe", b"MZ");
+
+        // the exec bit got lost
+        assert_eq!(Plan::new(&elf, false).unwrap().method, Method::ChmodAndRun);
+        assert_eq!(
+            Plan::new(&script, false).unwrap().method,
+            Method::Interpreter(vec!["/bin/sh".to_string()])
+        );
+        assert_eq!(
+            Plan::new(&plain, false).unwrap().words(&plain),
+            ["sh".to_string(), plain.to_string_lossy().into_owned()]
+        );
+
+        for path in [&elf, &script] {
+            make_executable(path).unwrap();
+
+            assert_eq!(Plan::new(path, false).unwrap().method, Method::Direct);
+        }
+
+        assert!(matches!(Plan::new(&exe, false), Err(Error::NeedsRunner(_))));
+        assert_eq!(Plan::new(&exe, true).unwrap().method, Method::Wine);
+
+        // a native program isn't handed to Wine just because a runner is set
+        for path in [&elf, &script, &plain] {
+            assert!(matches!(
+                Plan::new(path, true),
+                Err(Error::RunsNatively(native, _)) if native == *path
+            ));
+        }
+        assert!(matches!(
+            Plan::new(&dir, false),
+            Err(Error::NotExecutable(_))
+        ));
+
+        let jar = file(&dir, "game.jar", b"PK\x03\x04");
+
+        match find_program("java") {
+            Some(_) => assert_eq!(
+                Plan::new(&jar, false).unwrap().words(&jar),
+                ["java", "-jar", &jar.to_string_lossy()]
+            ),
+            None => assert!(matches!(
+                Plan::new(&jar, false),
+                Err(Error::MissingInterpreter(program, _)) if program == "java"
+            )),
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_follows_changes() {
+        let dir = temp_dir("detect-cache");
+
+        let path = file(&dir, "game", &elf(b""));
+
+        assert_eq!(detect_cached(&path).unwrap(), FileType::Elf);
+
+        std::fs::write(&path, "MZ").unwrap();
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+
+        assert_eq!(detect_cached(&path).unwrap(), FileType::Windows);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn needs_the_interpreter() {
+        let dir = temp_dir("detect-missing");
+
+        let env = file(
+            &dir,
+            "env",
+            b"#!/usr/bin/env gamelunch-no-such-interpreter\n",
+        );
+        let direct = file(&dir, "direct", b"#!/no/such/interpreter\n");
+
+        for (path, expected) in [
+            (&env, "gamelunch-no-such-interpreter"),
+            (&direct, "/no/such/interpreter"),
+        ] {
+            assert!(matches!(
+                Plan::new(path, false),
+                Err(Error::MissingInterpreter(program, _)) if program == expected
+            ));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
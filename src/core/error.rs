@@ -0,0 +1,126 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::core::format::format_time;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    MissingExecutable(PathBuf),
+    MissingName,
+    MissingAuthor,
+    MissingDirectory(PathBuf),
+    InvalidEnv(String),
+    GameNotFound(String),
+    InvalidRule(String),
+    InvalidSchedule(String),
+    InvalidTarget(String),
+    InvalidWrapper(String),
+    UnknownWrapper(String),
+    InvalidRunner(String),
+    UnknownRunner(String),
+    Prefix(String),
+    Launch(PathBuf, std::io::Error),
+    NotExecutable(PathBuf),
+    NeedsRunner(PathBuf),
+    RunsNatively(PathBuf, String),
+    MissingInterpreter(String, PathBuf),
+    OverBudget(String),
+    Lock(String),
+    WrongSecret,
+    LockedOut(u64),
+    MissingDecoy,
+    Control(String),
+    Storage(String),
+    DataDir(String),
+    UnsupportedVersion(u64),
+    Usage(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingExecutable(_) => write!(f, "Game does not exist"),
+            Error::MissingName => write!(f, "Game requires a name"),
+            Error::MissingAuthor => write!(f, "Game requires an author"),
+            Error::MissingDirectory(dir) => {
+                write!(f, "Working directory {} does not exist", dir.display())
+            }
+            Error::InvalidEnv(key) => write!(f, "Invalid environment variable name {:?}", key),
+            Error::GameNotFound(name) => write!(f, "No game named {}", name),
+            Error::InvalidRule(err) => write!(f, "Invalid match rule: {}", err),
+            Error::InvalidSchedule(err) => write!(f, "Invalid schedule: {}", err),
+            Error::InvalidTarget(err) => write!(f, "Invalid launch target: {}", err),
+            Error::InvalidWrapper(err) => write!(f, "Invalid wrapper: {}", err),
+            Error::UnknownWrapper(name) => write!(f, "No wrapper profile named {}", name),
+            Error::InvalidRunner(err) => write!(f, "Invalid runner: {}", err),
+            Error::UnknownRunner(name) => write!(f, "No Wine or Proton runner named {}", name),
+            Error::Prefix(err) => write!(f, "Wine prefix: {}", err),
+            Error::Launch(path, err) => {
+                write!(f, "Failed to launch {}: {}", path.display(), err)
+            }
+            Error::NotExecutable(path) => write!(
+                f,
+                "{} is not a program, script, Java archive or Windows program",
+                path.display()
+            ),
+            Error::NeedsRunner(path) => write!(
+                f,
+                "{} is a Windows program, choose a Wine or Proton runner for it",
+                path.display()
+            ),
+            Error::RunsNatively(path, file_type) => write!(
+                f,
+                "{} is a {}, run it natively instead of through Wine or Proton",
+                path.display(),
+                file_type
+            ),
+            Error::MissingInterpreter(program, path) => {
+                write!(
+                    f,
+                    "{} is needed to run {} but was not found",
+                    program,
+                    path.display()
+                )
+            }
+            Error::OverBudget(limit) => write!(f, "No playtime left, the {} is used up", limit),
+            Error::Lock(err) => write!(f, "Lock: {}", err),
+            Error::WrongSecret => write!(f, "Wrong PIN or passphrase"),
+            Error::LockedOut(wait) => {
+                write!(
+                    f,
+                    "Too many wrong guesses, try again in {}",
+                    format_time(wait)
+                )
+            }
+            Error::MissingDecoy => write!(f, "No decoy program or document is set"),
+            Error::Control(err) => write!(f, "Control socket: {}", err),
+            Error::Storage(err) => write!(f, "Failed to read saved data: {}", err),
+            Error::DataDir(err) => write!(f, "Data directory: {}", err),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "Saved data is version {}, which is newer than this launcher supports",
+                version
+            ),
+            Error::Usage(args) => write!(f, "Invalid arguments: {}", args),
+            Error::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Launch(_, err) | Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,45 @@
+//! Builders shared by the core's tests, so each test only spells out what
+//! it is about.
+
+use std::path::{Path, PathBuf};
+
+use crate::core::matching::ProcessInfo;
+use crate::core::target::LaunchTarget;
+use crate::core::Game;
+
+/// A game called `name` that starts `location`, with nothing else set.
+pub fn game(name: &str, location: LaunchTarget) -> Game {
+    Game {
+        name: name.to_string(),
+        author: "Someone".to_string(),
+        location,
+        ..Default::default()
+    }
+}
+
+/// A process running `cmd` under the tracker key `key`. Like a scanned one,
+/// its name is the file name of the key and it only has an executable when
+/// the key is a path.
+pub fn process(pid: u32, parent: Option<u32>, key: &str, cmd: &str) -> ProcessInfo {
+    ProcessInfo {
+        pid,
+        parent,
+        name: key.rsplit('/').next().unwrap_or(key).to_string(),
+        exe: Path::new(key).is_absolute().then(|| key.into()),
+        key: key.to_string(),
+        cmd: cmd.to_string(),
+        args: cmd.split_whitespace().skip(1).map(str::to_string).collect(),
+    }
+}
+
+/// An empty folder for the test `name`, left over from earlier runs or not.
+/// The test removes it when done.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gamelunch-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
@@ -0,0 +1,46 @@
+//! How times and durations are shown to the user, shared by the window,
+//! the command line and error messages.
+
+use chrono::TimeZone;
+
+/// Formats a unix timestamp as a local date and time.
+pub fn format_date(time: u64) -> String {
+    chrono::Local
+        .timestamp_opt(time as i64, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Formats seconds as `mm:ss`, or `h:mm:ss` from an hour on.
+pub fn format_duration(seconds: u64) -> String {
+    match seconds / 3600 {
+        0 => format!("{:02}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// Formats seconds in the largest whole unit: seconds, minutes or hours.
+pub fn format_time(time: &u64) -> String {
+    if *time < 60 {
+        format!("{} seconds", time)
+    } else if *time < 3600 {
+        format!("{} minutes", time / 60)
+    } else {
+        format!("{} hours", time / 3600)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(65), "01:05");
+        assert_eq!(format_duration(3 * 3600 + 65), "3:01:05");
+
+        assert_eq!(format_time(&59), "59 seconds");
+        assert_eq!(format_time(&(90 * 60)), "1 hours");
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::core::detect::{self, Method, Plan};
+use crate::core::process::{self, Signal};
+use crate::core::runner::{self, Runner};
+use crate::core::settings::{Settings, WorkingDirPolicy};
+use crate::core::target::LaunchTarget;
+use crate::core::wrappers::{self, Wrapper};
+use crate::core::{Error, Game, Result};
+
+/// Everything needed to start a game: its executable behind any wrappers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandLine {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Variables to set, or to remove when `None`.
+    pub env: BTreeMap<String, Option<String>>,
+    pub working_dir: Option<PathBuf>,
+    /// A file that needs its exec bit set first, see `Method::ChmodAndRun`.
+    pub make_executable: Option<PathBuf>,
+}
+
+impl CommandLine {
+    /// `game` started with `args`, run through `wrappers` outermost first,
+    /// then `runner` with its prefix. The game's own environment wins over
+    /// the prefix's, which wins over the wrappers'. `policy` picks the
+    /// working directory if the game has none.
+    pub fn new(
+        game: &Game,
+        args: &[String],
+        wrappers: &[&Wrapper],
+        runner: Option<(&Runner, &Path)>,
+        policy: WorkingDirPolicy,
+    ) -> Result<Self> {
+        let mut words: Vec<String> = Vec::new();
+        let mut env = BTreeMap::new();
+
+        for wrapper in wrappers {
+            words.push(wrapper.program.clone());
+            words.extend(wrapper.args.iter().cloned());
+
+            for (key, value) in &wrapper.env {
+                env.insert(key.clone(), Some(value.clone()));
+            }
+        }
+
+        if let Some((runner, prefix)) = runner {
+            let (prefix_words, prefix_env) = runner.command(prefix);
+
+            words.extend(prefix_words);
+            env.extend(
+                prefix_env
+                    .into_iter()
+                    .map(|(key, value)| (key, Some(value))),
+            );
+        }
+
+        if let Some(wine) = &game.wine {
+            env.extend(
+                wine.env
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Some(value.clone()))),
+            );
+        }
+
+        let (target_words, make_executable) = target_words(&game.location, args, runner.is_some())?;
+
+        words.extend(target_words);
+
+        env.extend(game.env.clone());
+
+        Ok(Self {
+            program: words.remove(0).into(),
+            args: words,
+            env,
+            working_dir: game.working_dir(policy),
+            make_executable,
+        })
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+
+        command.args(&self.args);
+
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+
+        command
+    }
+}
+
+/// The command as it could be typed into a shell.
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(dir) = &self.working_dir {
+            write!(
+                f,
+                "cd {} && ",
+                join_args(&[dir.to_string_lossy().into_owned()])
+            )?;
+        }
+
+        if let Some(path) = &self.make_executable {
+            write!(
+                f,
+                "chmod u+x {} && ",
+                join_args(&[path.to_string_lossy().into_owned()])
+            )?;
+        }
+
+        if !self.env.is_empty() {
+            let mut words = vec!["env".to_string()];
+
+            for (key, value) in &self.env {
+                match value {
+                    Some(value) => words.push(format!("{}={}", key, value)),
+                    None => words.extend(["-u".to_string(), key.clone()]),
+                }
+            }
+
+            write!(f, "{} ", join_args(&words))?;
+        }
+
+        let mut words = vec![self.program.to_string_lossy().into_owned()];
+
+        words.extend(self.args.iter().cloned());
+
+        write!(f, "{}", join_args(&words))
+    }
+}
+
+/// A child process started for a game.
+pub struct Launched {
+    child: Child,
+    /// Name of the game, for `Exited`.
+    pub name: String,
+    pub key: String,
+    pub args: Vec<String>,
+    pub started: Instant,
+    /// `stop` already sent SIGTERM.
+    pub stopping: bool,
+}
+
+impl Launched {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+/// How a launched game ended.
+#[derive(Clone, Debug)]
+pub struct Exited {
+    pub game: String,
+    /// Tracker key of the game.
+    pub key: String,
+    pub pid: u32,
+    pub code: Option<i32>,
+    /// The signal that killed it, on Unix.
+    pub signal: Option<i32>,
+}
+
+/// Owns the child processes of games started from the launcher.
+#[derive(Default)]
+pub struct Launcher {
+    /// Running children by game id, oldest first.
+    games: HashMap<Uuid, Vec<Launched>>,
+    opened: Vec<Child>,
+    exited: Vec<Exited>,
+    wrappers: Vec<Wrapper>,
+    runners: Vec<Runner>,
+    working_dir_policy: WorkingDirPolicy,
+}
+
+impl Launcher {
+    /// Takes the wrapper profiles, runners and working directory policy
+    /// games are launched with from `settings`.
+    pub fn configure(&mut self, settings: &Settings) {
+        self.wrappers = settings.wrappers.clone();
+        self.runners = settings.runners.clone();
+        self.working_dir_policy = settings.working_dir_policy;
+    }
+
+    /// What `launch_with` would run, without running it.
+    pub fn command_line(&self, game: &Game, args: &[String]) -> Result<CommandLine> {
+        let wrappers = wrappers::resolve(&self.wrappers, game)?;
+
+        let Some(runner) = runner::resolve(&self.runners, game)? else {
+            return CommandLine::new(game, args, &wrappers, None, self.working_dir_policy);
+        };
+
+        let prefix = runner::prefix(game)
+            .ok_or_else(|| Error::Prefix("no data directory to keep prefixes in".to_string()))?;
+
+        CommandLine::new(
+            game,
+            args,
+            &wrappers,
+            Some((runner, &prefix)),
+            self.working_dir_policy,
+        )
+    }
+
+    /// The runner of `game`, `None` for native games.
+    pub fn runner(&self, game: &Game) -> Result<Option<&Runner>> {
+        runner::resolve(&self.runners, game)
+    }
+
+    /// Starts `game` with its arguments, environment and working directory
+    /// in a new process group and returns its pid, which is also the group
+    /// id. Games handed to the system opener return `None` and aren't
+    /// followed, the opener exits long before the game does.
+    pub fn launch(&mut self, game: &Game) -> Result<Option<u32>> {
+        self.launch_with(game, &game.args)
+    }
+
+    /// Like `launch`, passing `args` instead of the game's own.
+    pub fn launch_with(&mut self, game: &Game, args: &[String]) -> Result<Option<u32>> {
+        let line = self.command_line(game, args)?;
+
+        if let Some(path) = &line.make_executable {
+            detect::make_executable(path)?;
+        }
+
+        let mut command = line.command();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+
+            command.process_group(0);
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+
+            command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+        }
+
+        let child = command
+            .spawn()
+            .map_err(|err| Error::Launch(line.program.clone(), err))?;
+
+        if game.location.through_opener() {
+            self.opened.push(child);
+
+            return Ok(None);
+        }
+
+        let pid = child.id();
+
+        self.games.entry(game.id).or_default().push(Launched {
+            child,
+            name: game.name.clone(),
+            key: game.key(),
+            args: args.to_vec(),
+            started: Instant::now(),
+            stopping: false,
+        });
+
+        Ok(Some(pid))
+    }
+
+    /// Runs the program at `path`, or opens any other file or folder with
+    /// its default application. Used for the panic decoy, which is kept apart
+    /// from the games so panicking again doesn't touch it, and to show
+    /// prefixes.
+    pub fn open(&mut self, path: &Path) -> Result<u32> {
+        let mut command = match detect::is_executable(path) {
+            true => Command::new(path),
+            false => {
+                let opener = opener();
+                let mut command = Command::new(&opener[0]);
+
+                command.args(&opener[1..]).arg(path);
+                command
+            }
+        };
+
+        let proc = command
+            .spawn()
+            .map_err(|err| Error::Launch(path.to_path_buf(), err))?;
+
+        let pid = proc.id();
+
+        self.opened.push(proc);
+
+        Ok(pid)
+    }
+
+    /// Follows `game` after it was edited, so exits of its running copies
+    /// are recorded under the new key.
+    pub fn update(&mut self, game: &Game) {
+        for proc in self.games.get_mut(&game.id).into_iter().flatten() {
+            proc.name = game.name.clone();
+            proc.key = game.key();
+        }
+    }
+
+    /// Pids of children that haven't been reaped yet.
+    pub fn pids(&self) -> Vec<u32> {
+        self.games.values().flatten().map(Launched::pid).collect()
+    }
+
+    /// Pids of the unreaped children of the game with id `game`.
+    pub fn game_pids(&self, game: Uuid) -> Vec<u32> {
+        self.games
+            .get(&game)
+            .into_iter()
+            .flatten()
+            .map(Launched::pid)
+            .collect()
+    }
+
+    /// The oldest unreaped child of the game with id `game`.
+    pub fn running(&self, game: Uuid) -> Option<&Launched> {
+        self.games.get(&game).and_then(|procs| procs.first())
+    }
+
+    /// The arguments a still unreaped child of the game with id `game` was
+    /// started with.
+    pub fn args(&self, game: Uuid) -> Option<&[String]> {
+        self.running(game).map(|proc| proc.args.as_slice())
+    }
+
+    /// Asks every copy of the game with id `game` to exit, or kills them if
+    /// that was already asked.
+    pub fn stop(&mut self, game: Uuid) {
+        for proc in self.games.get_mut(&game).into_iter().flatten() {
+            let signal = match proc.stopping {
+                true => Signal::Kill,
+                false => Signal::Term,
+            };
+
+            process::send_group(proc.pid(), signal);
+
+            proc.stopping = true;
+        }
+    }
+
+    /// Waits for children that have exited, so they don't linger as zombies.
+    /// Exits of games are kept for `take_exited`.
+    pub fn reap(&mut self) {
+        for procs in self.games.values_mut() {
+            procs.retain_mut(|proc| {
+                let Ok(Some(status)) = proc.child.try_wait() else {
+                    return true;
+                };
+
+                #[cfg(unix)]
+                let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+
+                #[cfg(not(unix))]
+                let signal = None;
+
+                self.exited.push(Exited {
+                    game: proc.name.clone(),
+                    key: proc.key.clone(),
+                    pid: proc.pid(),
+                    code: status.code(),
+                    signal,
+                });
+
+                false
+            });
+        }
+
+        self.games.retain(|_, procs| !procs.is_empty());
+
+        self.opened
+            .retain_mut(|proc| !matches!(proc.try_wait(), Ok(Some(_))));
+    }
+
+    /// Games that exited since the last call, see `reap`.
+    pub fn take_exited(&mut self) -> Vec<Exited> {
+        std::mem::take(&mut self.exited)
+    }
+}
+
+/// Splits a command line typed into the UI into arguments, at whitespace
+/// outside of single or double quotes. Outside of quotes a backslash keeps a
+/// quote or whitespace after it as is, other backslashes are left alone for
+/// Windows paths.
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '\\')
+                if chars
+                    .peek()
+                    .is_some_and(|next| matches!(next, '"' | '\'') || next.is_whitespace()) =>
+            {
+                arg.get_or_insert_with(String::new).extend(chars.next());
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    args.extend(arg);
+
+    args
+}
+
+/// The reverse of `split_args`, single quoting arguments the way a POSIX
+/// shell reads them.
+pub fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-+=:,./@%".contains(c));
+
+            match plain {
+                true => arg.clone(),
+                false => format!("'{}'", arg.replace('\'', "'\\''")),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Refuses environment variable names the OS can't take.
+pub fn validate_env_key(key: &str) -> Result<()> {
+    match key.is_empty() || key.contains(['=', '\0']) {
+        true => Err(Error::InvalidEnv(key.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Reaps the children of `launcher` every second on a background thread,
+/// passing the exits of games to `on_exit`.
+pub fn reap_in_background(
+    launcher: Arc<Mutex<Launcher>>,
+    on_exit: impl Fn(Exited) + Send + 'static,
+) {
+    std::thread::spawn(move || loop {
+        let exited = {
+            let mut launcher = launcher.lock().unwrap();
+
+            launcher.reap();
+            launcher.take_exited()
+        };
+
+        for exited in exited {
+            on_exit(exited);
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+    });
+}
+
+/// The command that opens a file or URL passed after it with the desktop's
+/// default application.
+fn opener() -> Vec<String> {
+    #[cfg(target_os = "macos")]
+    let words = ["open"];
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let words = ["xdg-open"];
+
+    #[cfg(not(unix))]
+    let words = ["cmd", "/C", "start", ""];
+
+    words.iter().map(|word| word.to_string()).collect()
+}
+
+/// The command line starting `target` with `args`, and the file to make
+/// executable first if any. `runner` says whether a Wine or Proton runner
+/// goes in front.
+fn target_words(
+    target: &LaunchTarget,
+    args: &[String],
+    runner: bool,
+) -> Result<(Vec<String>, Option<PathBuf>)> {
+    let mut words = Vec::new();
+    let mut make_executable = None;
+
+    let mut program = |path: &Path, words: &mut Vec<String>| -> Result<()> {
+        let plan = Plan::new(path, runner)?;
+
+        if plan.method == Method::ChmodAndRun {
+            make_executable = Some(path.to_path_buf());
+        }
+
+        words.extend(plan.words(path));
+
+        Ok(())
+    };
+
+    match target {
+        LaunchTarget::Executable(path) => {
+            program(path, &mut words)?;
+            words.extend(args.iter().cloned());
+        }
+        LaunchTarget::Shell(command) => {
+            // the arguments are passed on as "$@", or appended for cmd.
+            // Without any the command is left alone, "$@" would end up on
+            // the last part of a pipe or && chain, or after a redirect
+            #[cfg(unix)]
+            words.extend([
+                "sh".to_string(),
+                "-c".to_string(),
+                match args.is_empty() {
+                    true => command.clone(),
+                    false => format!("{} \"$@\"", command),
+                },
+                "sh".to_string(),
+            ]);
+
+            #[cfg(not(unix))]
+            words.extend(["cmd".to_string(), "/C".to_string(), command.clone()]);
+
+            words.extend(args.iter().cloned());
+        }
+        LaunchTarget::Url(url) => {
+            words.extend(opener());
+            words.push(url.clone());
+        }
+        LaunchTarget::Flatpak(id) => {
+            words.extend(["flatpak".to_string(), "run".to_string(), id.clone()]);
+            words.extend(args.iter().cloned());
+        }
+        LaunchTarget::Steam(_) => {
+            words.extend(opener());
+            words.push(target.to_string());
+        }
+        LaunchTarget::Emulator { emulator, rom } => {
+            program(emulator, &mut words)?;
+            words.extend(args.iter().cloned());
+            words.push(rom.to_string_lossy().into_owned());
+        }
+    }
+
+    Ok((words, make_executable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_args_quotes_for_the_shell() {
+        let args: Vec<String> = ["run", "", "two words", "it's", "$HOME", "a;b", "say \"hi\""]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        let line = join_args(&args);
+
+        assert_eq!(
+            line,
+            r#"run '' 'two words' 'it'\''s' '$HOME' 'a;b' 'say "hi"'"#
+        );
+        assert_eq!(split_args(&line), args);
+    }
+
+    #[test]
+    fn split_args_keeps_windows_paths() {
+        assert_eq!(
+            split_args(r"C:\Games\game.exe --level\ 2"),
+            [r"C:\Games\game.exe", "--level 2"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_commands_only_get_arguments_if_there_are_some() {
+        let target = LaunchTarget::Shell("game | tee log".to_string());
+
+        let (words, _) = target_words(&target, &[], false).unwrap();
+
+        assert_eq!(words, ["sh", "-c", "game | tee log", "sh"]);
+
+        let (words, _) = target_words(&target, &["--fast".to_string()], false).unwrap();
+
+        assert_eq!(words, ["sh", "-c", "game | tee log \"$@\"", "sh", "--fast"]);
+    }
+}
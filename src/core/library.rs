@@ -0,0 +1,401 @@
+use std::path::Path;
+
+use uuid::Uuid;
+
+use crate::core::launcher::validate_env_key;
+use crate::core::session::{History, Session};
+use crate::core::target::LaunchTarget;
+use crate::core::{Error, Game, Result};
+
+/// How long removed games stay in the trash.
+pub const TRASH_DAYS: u64 = 30;
+
+/// Saved flattened into the library file, as its `games` and `trash`.
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone, Debug)]
+pub struct Library {
+    games: Vec<Game>,
+    #[serde(default)]
+    trash: Vec<Trashed>,
+}
+
+/// A removed game with the playtime it took along.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Trashed {
+    pub game: Game,
+    /// Unix timestamp of the removal.
+    pub removed: u64,
+    /// Where it was in the library, so restoring puts it back there.
+    pub index: usize,
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+    #[serde(default)]
+    pub imported_time: u64,
+}
+
+impl Trashed {
+    pub fn seconds(&self) -> u64 {
+        self.imported_time
+            + self
+                .sessions
+                .iter()
+                .map(|session| session.seconds)
+                .sum::<u64>()
+    }
+}
+
+impl Library {
+    pub fn games(&self) -> &[Game] {
+        &self.games
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Game> {
+        self.games.iter().find(|game| game.name == name)
+    }
+
+    pub fn find(&self, id: Uuid) -> Option<&Game> {
+        self.games.iter().find(|game| game.id == id)
+    }
+
+    fn position(&self, id: Uuid) -> Result<usize> {
+        self.games
+            .iter()
+            .position(|game| game.id == id)
+            .ok_or_else(|| Error::GameNotFound(id.to_string()))
+    }
+
+    /// Checks a game the same way the Add Game page does.
+    pub fn validate(game: &Game) -> Result<()> {
+        game.location.validate()?;
+
+        if game.name.is_empty() {
+            Err(Error::MissingName)
+        } else if game.author.is_empty() {
+            Err(Error::MissingAuthor)
+        } else {
+            for rule in &game.rules {
+                rule.validate()?;
+            }
+
+            if let Some(dir) = &game.working_dir {
+                if !dir.is_dir() {
+                    return Err(Error::MissingDirectory(dir.clone()));
+                }
+            }
+
+            if !game.args.is_empty() && !game.location.takes_args() {
+                return Err(Error::InvalidTarget(format!(
+                    "{} targets take no arguments",
+                    game.location.kind()
+                )));
+            }
+
+            if let Some(wine) = &game.wine {
+                if !matches!(game.location, LaunchTarget::Executable(_)) {
+                    return Err(Error::InvalidRunner(
+                        "only executables run through Wine".to_string(),
+                    ));
+                }
+
+                if wine.runner.trim().is_empty() {
+                    return Err(Error::InvalidRunner("no runner chosen".to_string()));
+                }
+
+                for key in wine.env.keys() {
+                    validate_env_key(key)?;
+                }
+            }
+
+            for key in game.env.keys() {
+                validate_env_key(key)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Adds `game`, giving it an id unless it has one of its own.
+    pub fn add(&mut self, mut game: Game) -> Result<()> {
+        Self::validate(&game)?;
+
+        if game.id.is_nil() || self.find(game.id).is_some() {
+            game.id = Uuid::new_v4();
+        }
+
+        self.games.push(game);
+
+        Ok(())
+    }
+
+    /// Replaces the game with id `id`, checked like `add`. Rules pointing at
+    /// files the game moved away from are pointed at the new ones. Returns
+    /// the old game, whose `key` its playtime is recorded under.
+    pub fn edit(&mut self, id: Uuid, mut game: Game) -> Result<Game> {
+        let index = self.position(id)?;
+        let old = &self.games[index];
+
+        let moved: Vec<(&Path, &Path)> = match (&old.location, &game.location) {
+            (
+                LaunchTarget::Emulator { emulator, rom },
+                LaunchTarget::Emulator {
+                    emulator: new_emulator,
+                    rom: new_rom,
+                },
+            ) => vec![(emulator, new_emulator), (rom, new_rom)],
+            (old, new) => old.executable().zip(new.executable()).into_iter().collect(),
+        };
+
+        for (from, to) in moved {
+            if from != to {
+                game.rules = game
+                    .rules
+                    .iter()
+                    .map(|rule| rule.retarget(from, to))
+                    .collect();
+            }
+        }
+
+        Self::validate(&game)?;
+
+        game.id = id;
+
+        Ok(std::mem::replace(&mut self.games[index], game))
+    }
+
+    /// Moves the game with id `id` to the trash, taking its playtime out of
+    /// `history` unless another game shares its key.
+    pub fn remove(&mut self, id: Uuid, history: &mut History, now: u64) -> Result<&Trashed> {
+        let index = self.position(id)?;
+        let game = self.games.remove(index);
+        let key = game.key();
+
+        let (sessions, imported_time) = match self.games.iter().any(|other| other.key() == key) {
+            true => (Vec::new(), 0),
+            false => history.take(&key),
+        };
+
+        self.trash.push(Trashed {
+            game,
+            removed: now,
+            index,
+            sessions,
+            imported_time,
+        });
+
+        Ok(&self.trash[self.trash.len() - 1])
+    }
+
+    pub fn remove_by_name(
+        &mut self,
+        name: &str,
+        history: &mut History,
+        now: u64,
+    ) -> Result<&Trashed> {
+        let id = self
+            .get(name)
+            .ok_or_else(|| Error::GameNotFound(name.to_string()))?
+            .id;
+
+        self.remove(id, history, now)
+    }
+
+    /// Removed games, oldest first.
+    pub fn trash(&self) -> &[Trashed] {
+        &self.trash
+    }
+
+    /// Takes the game with id `id` out of the trash and puts it and its
+    /// playtime back. It isn't checked again, so a game whose executable is
+    /// gone can still be restored and fixed.
+    pub fn restore(&mut self, id: Uuid, history: &mut History) -> Result<&Game> {
+        let position = self
+            .trash
+            .iter()
+            .position(|trashed| trashed.game.id == id)
+            .ok_or_else(|| Error::GameNotFound(id.to_string()))?;
+
+        let trashed = self.trash.remove(position);
+        let index = trashed.index.min(self.games.len());
+
+        history.put_back(&trashed.game.key(), trashed.sessions, trashed.imported_time);
+
+        self.games.insert(index, trashed.game);
+
+        Ok(&self.games[index])
+    }
+
+    /// Deletes the game with id `id` from the trash for good.
+    pub fn discard(&mut self, id: Uuid) -> Result<Trashed> {
+        let position = self
+            .trash
+            .iter()
+            .position(|trashed| trashed.game.id == id)
+            .ok_or_else(|| Error::GameNotFound(id.to_string()))?;
+
+        Ok(self.trash.remove(position))
+    }
+
+    /// Deletes games removed more than `TRASH_DAYS` before `now`, or all of
+    /// them with `None`. Returns how many were deleted.
+    pub fn empty_trash(&mut self, now: Option<u64>) -> usize {
+        let before = self.trash.len();
+
+        match now {
+            Some(now) => self
+                .trash
+                .retain(|trashed| now.saturating_sub(trashed.removed) < TRASH_DAYS * 24 * 60 * 60),
+            None => self.trash.clear(),
+        }
+
+        before - self.trash.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::{game, temp_dir};
+    use crate::core::matching::MatchRule;
+
+    #[test]
+    fn edit_follows_the_executable() {
+        let dir = temp_dir("library");
+        let (old, new) = (dir.join("old").join("game"), dir.join("new").join("run"));
+
+        for path in [&old, &new] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let game = Game {
+            rules: vec![
+                MatchRule::ExeName("game".to_string()),
+                MatchRule::ExePath(format!("{}/*", dir.join("old").display())),
+                MatchRule::CommandLine(format!(
+                    "^{} --fast$",
+                    regex::escape(&old.to_string_lossy())
+                )),
+                MatchRule::ExeName("launcher".to_string()),
+            ],
+            ..game("Game", LaunchTarget::Executable(old.clone()))
+        };
+
+        let mut library = Library::default();
+        let mut history = History::default();
+
+        library.add(game.clone()).unwrap();
+
+        let id = library.games()[0].id;
+
+        history.record_for_test(&game.key(), 100, 60);
+
+        let old_game = library
+            .edit(
+                id,
+                Game {
+                    location: LaunchTarget::Executable(new.clone()),
+                    ..game.clone()
+                },
+            )
+            .unwrap();
+
+        history.rename(&old_game.key(), &library.games()[0].key());
+
+        let edited = library.find(id).unwrap();
+
+        assert_eq!(
+            edited.rules,
+            [
+                MatchRule::ExeName("run".to_string()),
+                MatchRule::ExePath(format!("{}/*", dir.join("new").display())),
+                MatchRule::CommandLine(format!(
+                    "^{} --fast$",
+                    regex::escape(&new.to_string_lossy())
+                )),
+                MatchRule::ExeName("launcher".to_string()),
+            ]
+        );
+        assert_eq!(history.total(&edited.key()), 60);
+        assert_eq!(history.total(&old_game.key()), 0);
+
+        // a broken edit leaves the game alone
+        let broken = Game {
+            author: "".to_string(),
+            ..edited.clone()
+        };
+
+        assert!(library.edit(id, broken).is_err());
+        assert_eq!(
+            library.find(id).unwrap().location,
+            LaunchTarget::Executable(new)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trash_keeps_playtime() {
+        let mut library = Library::default();
+        let mut history = History::default();
+
+        for game in [
+            game("First", LaunchTarget::Steam(1)),
+            game("Second", LaunchTarget::Steam(2)),
+            game("Third", LaunchTarget::Steam(3)),
+        ] {
+            library.add(game).unwrap();
+        }
+
+        history.record_for_test("steam://rungameid/2", 100, 60);
+        history.record_for_test("steam://rungameid/3", 100, 30);
+
+        let id = library.get("Second").unwrap().id;
+        let removed = library.remove(id, &mut history, 1000).unwrap();
+
+        assert_eq!((removed.index, removed.seconds()), (1, 60));
+        assert_eq!(history.total("steam://rungameid/2"), 0);
+
+        library.restore(id, &mut history).unwrap();
+
+        let names: Vec<&str> = library
+            .games()
+            .iter()
+            .map(|game| game.name.as_str())
+            .collect();
+
+        // back in its place, with the time it took along
+        assert_eq!(names, ["First", "Second", "Third"]);
+        assert_eq!(library.games()[1].id, id);
+        assert_eq!(history.total("steam://rungameid/2"), 60);
+        assert!(library.trash().is_empty());
+    }
+
+    #[test]
+    fn empty_trash_only_drops_old_games() {
+        let mut library = Library::default();
+        let mut history = History::default();
+
+        for game in [
+            game("Old", LaunchTarget::Steam(1)),
+            game("New", LaunchTarget::Steam(2)),
+        ] {
+            library.add(game).unwrap();
+        }
+
+        history.record_for_test("steam://rungameid/2", 100, 60);
+
+        let day = 24 * 60 * 60;
+
+        library.remove_by_name("Old", &mut history, 0).unwrap();
+        library
+            .remove_by_name("New", &mut history, 20 * day)
+            .unwrap();
+
+        assert_eq!(library.empty_trash(Some(TRASH_DAYS * day)), 1);
+        assert_eq!(library.trash().len(), 1);
+        assert_eq!(library.trash()[0].seconds(), 60);
+
+        assert_eq!(library.empty_trash(None), 1);
+        assert!(library.trash().is_empty());
+        assert_eq!(history.total("steam://rungameid/2"), 0);
+    }
+}
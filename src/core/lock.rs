@@ -0,0 +1,322 @@
+//! Locked mode: a PIN or passphrase guarding the settings, editing and
+//! removing games and changes to recorded time, so budgets and schedules
+//! can't just be turned off. Only a salted argon2 hash is kept. Wrong guesses
+//! make the next one wait longer and every attempt goes to `audit.log` in
+//! the data directory. The count of wrong guesses lives next to it in
+//! `guesses.json` rather than in the library, which the launcher saves from
+//! its settings page.
+
+use std::path::{Path, PathBuf};
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+use crate::core::triggers::append_log;
+use crate::core::{Error, Result};
+
+/// Wrong guesses in a row before they are slowed down.
+pub const FREE_GUESSES: u32 = 3;
+
+/// Shortest PIN or passphrase accepted.
+pub const MIN_LENGTH: usize = 4;
+
+/// Seconds to wait after the first slowed down guess, doubled for each one
+/// after.
+const FIRST_WAIT: u64 = 30;
+
+const MAX_WAIT: u64 = 60 * 60;
+
+/// Names of the files kept in the data directory.
+pub const LOG_FILE: &str = "audit.log";
+pub const GUESSES_FILE: &str = "guesses.json";
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Lock {
+    /// PHC string of the argon2 hash, salt included. Nothing is locked when
+    /// `None`.
+    pub hash: Option<String>,
+}
+
+/// Wrong guesses in a row, shared by the window and the command line
+/// through `guesses.json`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Guesses {
+    pub failures: u32,
+    /// Unix time of the last wrong guess.
+    pub last_failure: u64,
+}
+
+impl Guesses {
+    /// Seconds until another guess is allowed at `now`.
+    pub fn wait(&self, now: u64) -> u64 {
+        if self.failures < FREE_GUESSES {
+            return 0;
+        }
+
+        let wait = (FIRST_WAIT << (self.failures - FREE_GUESSES).min(16)).min(MAX_WAIT);
+
+        // a clock set back can't make it longer than the wait itself
+        (self.last_failure + wait).saturating_sub(now).min(wait)
+    }
+
+    /// The guesses saved at `path`, none if there is no file. A broken
+    /// file counts as the most guesses, so it can't be used to start over.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or(Self {
+                failures: u32::MAX,
+                last_failure: crate::core::session::now(),
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_vec(self).map_err(|err| Error::Storage(err.to_string()))?;
+
+        std::fs::write(path, json)?;
+
+        Ok(())
+    }
+}
+
+impl Lock {
+    /// A lock opened by `secret`.
+    pub fn new(secret: &str) -> Result<Self> {
+        if secret.chars().count() < MIN_LENGTH {
+            return Err(Error::Lock(format!(
+                "use at least {} characters",
+                MIN_LENGTH
+            )));
+        }
+
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(secret.as_bytes(), &salt)
+            .map_err(lock)?;
+
+        Ok(Self {
+            hash: Some(hash.to_string()),
+        })
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.hash.is_some()
+    }
+
+    /// Checks `secret`, counting it in `guesses` if it doesn't match.
+    /// Refused without checking while the last wrong guess is too recent.
+    pub fn check(&self, guesses: &mut Guesses, secret: &str, now: u64) -> Result<()> {
+        let Some(hash) = &self.hash else {
+            return Ok(());
+        };
+
+        let wait = guesses.wait(now);
+
+        if wait > 0 {
+            return Err(Error::LockedOut(wait));
+        }
+
+        let hash = PasswordHash::new(hash).map_err(lock)?;
+
+        match Argon2::default().verify_password(secret.as_bytes(), &hash) {
+            Ok(()) => {
+                guesses.failures = 0;
+
+                Ok(())
+            }
+            Err(_) => {
+                guesses.failures = guesses.failures.saturating_add(1);
+                guesses.last_failure = now;
+
+                Err(Error::WrongSecret)
+            }
+        }
+    }
+
+    /// `check` against the saved guesses, with the attempt to do `action`
+    /// written to the audit log.
+    pub fn unlock(&self, secret: &str, action: &str, now: u64) -> Result<()> {
+        self.unlock_in(
+            crate::core::data::data_dir().as_deref(),
+            secret,
+            action,
+            now,
+        )
+    }
+
+    /// `unlock` with the guesses and audit log in `dir`.
+    fn unlock_in(&self, dir: Option<&Path>, secret: &str, action: &str, now: u64) -> Result<()> {
+        let path = dir.map(|dir| dir.join(GUESSES_FILE));
+        let mut guesses = path.as_deref().map(Guesses::load).unwrap_or_default();
+
+        let result = self.check(&mut guesses, secret, now);
+
+        if let Some(path) = &path {
+            if let Err(err) = guesses.save(path) {
+                log::warn!("Failed to save {}: {}", path.display(), err);
+            }
+        }
+
+        let outcome = match &result {
+            Ok(()) => "allowed".to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        audit_to(
+            dir.map(|dir| dir.join(LOG_FILE)).as_deref(),
+            now,
+            &format!("{}: {}", action, outcome),
+        );
+
+        result
+    }
+}
+
+fn lock(err: argon2::password_hash::Error) -> Error {
+    Error::Lock(err.to_string())
+}
+
+/// Where attempts to unlock are logged.
+pub fn log_path() -> Option<PathBuf> {
+    crate::core::data::data_dir().map(|dir| dir.join(LOG_FILE))
+}
+
+/// The wrong guesses so far, see `Guesses`.
+pub fn guesses() -> Guesses {
+    crate::core::data::data_dir()
+        .map(|dir| Guesses::load(&dir.join(GUESSES_FILE)))
+        .unwrap_or_default()
+}
+
+/// Writes `line` to the audit log, warning if it can't.
+pub fn audit(now: u64, line: &str) {
+    audit_to(log_path().as_deref(), now, line);
+}
+
+fn audit_to(log: Option<&Path>, now: u64, line: &str) {
+    if let Some(path) = log {
+        if let Err(err) = append_log(path, now, &[line.to_string()]) {
+            log::warn!("Failed to write the audit log: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::temp_dir;
+    use crate::core::triggers::read_log;
+
+    #[test]
+    fn checks_the_secret() {
+        assert!(matches!(Lock::new("123"), Err(Error::Lock(_))));
+
+        let lock = Lock::new("1234").unwrap();
+        let hash = lock.hash.clone().unwrap();
+        let mut guesses = Guesses::default();
+
+        // only the salted hash is kept, and every lock gets its own salt
+        assert!(!hash.contains("1234"));
+        assert_ne!(Lock::new("1234").unwrap().hash, Some(hash));
+
+        assert!(matches!(
+            lock.check(&mut guesses, "4321", 100),
+            Err(Error::WrongSecret)
+        ));
+        assert_eq!((guesses.failures, guesses.last_failure), (1, 100));
+
+        assert!(lock.check(&mut guesses, "1234", 100).is_ok());
+        assert_eq!(guesses.failures, 0);
+
+        // nothing is locked without a hash
+        assert!(Lock::default().check(&mut guesses, "anything", 100).is_ok());
+    }
+
+    #[test]
+    fn wrong_guesses_wait_longer() {
+        let lock = Lock::new("1234").unwrap();
+        let mut guesses = Guesses::default();
+
+        for _ in 0..FREE_GUESSES {
+            assert_eq!(guesses.wait(100), 0);
+            assert!(matches!(
+                lock.check(&mut guesses, "0000", 100),
+                Err(Error::WrongSecret)
+            ));
+        }
+
+        assert_eq!(guesses.wait(100), FIRST_WAIT);
+        assert_eq!(guesses.wait(100 + FIRST_WAIT / 2), FIRST_WAIT / 2);
+
+        // refused without counting, even with the right secret
+        assert!(matches!(
+            lock.check(&mut guesses, "1234", 110),
+            Err(Error::LockedOut(20))
+        ));
+        assert_eq!(guesses.failures, FREE_GUESSES);
+
+        let now = 100 + FIRST_WAIT;
+
+        assert!(matches!(
+            lock.check(&mut guesses, "0000", now),
+            Err(Error::WrongSecret)
+        ));
+        assert_eq!(guesses.wait(now), FIRST_WAIT * 2);
+
+        // a clock set back doesn't make it longer
+        assert_eq!(guesses.wait(0), FIRST_WAIT * 2);
+
+        guesses.failures = 100;
+
+        assert_eq!(guesses.wait(now), MAX_WAIT);
+
+        guesses.failures = FREE_GUESSES + 1;
+
+        assert!(lock
+            .check(&mut guesses, "1234", now + FIRST_WAIT * 2)
+            .is_ok());
+        assert_eq!(guesses.wait(now + FIRST_WAIT * 2), 0);
+    }
+
+    #[test]
+    fn keeps_guesses_and_logs_every_attempt() {
+        let dir = temp_dir("audit");
+        let lock = Lock::new("1234").unwrap();
+
+        for _ in 0..FREE_GUESSES {
+            let _ = lock.unlock_in(Some(&dir), "0000", "Edit Celeste", 100);
+        }
+
+        // counted across runs, whatever the library says
+        assert!(matches!(
+            lock.unlock_in(Some(&dir), "1234", "Edit Celeste", 110),
+            Err(Error::LockedOut(_))
+        ));
+
+        assert!(lock
+            .unlock_in(Some(&dir), "1234", "Edit Celeste", 100 + FIRST_WAIT)
+            .is_ok());
+        assert_eq!(Guesses::load(&dir.join(GUESSES_FILE)).failures, 0);
+
+        // a broken file doesn't start the count over
+        std::fs::write(dir.join(GUESSES_FILE), "{").unwrap();
+
+        assert!(Guesses::load(&dir.join(GUESSES_FILE)).wait(crate::core::session::now()) > 0);
+
+        let lines = read_log(&dir.join(LOG_FILE), 10);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines.len(), FREE_GUESSES as usize + 2);
+        assert!(lines[0].ends_with("Edit Celeste: Wrong PIN or passphrase"));
+        assert!(lines.last().unwrap().ends_with("Edit Celeste: allowed"));
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::core::detect::{detect_cached, FileType};
+use crate::core::library::Library;
+use crate::core::target::LaunchTarget;
+use crate::core::tracker::process_key;
+use crate::core::{Error, Game, Result};
+
+/// Extra ways to recognize a game's processes besides its executable path,
+/// for games started through launchers, scripts or Wine.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum MatchRule {
+    /// Process name, compared case-insensitively.
+    ExeName(String),
+    /// Glob matched against the executable path.
+    ExePath(String),
+    /// Regex searched in the space-separated command line.
+    CommandLine(String),
+    /// Any process started, directly or not, by the launched game.
+    Descendants,
+}
+
+impl MatchRule {
+    pub const KINDS: [&'static str; 4] = ["exe_name", "exe_path", "command_line", "descendants"];
+
+    pub fn new(kind: &str, value: &str) -> Result<Self> {
+        let rule = match kind {
+            "exe_name" => MatchRule::ExeName(value.to_string()),
+            "exe_path" => MatchRule::ExePath(value.to_string()),
+            "command_line" => MatchRule::CommandLine(value.to_string()),
+            "descendants" => MatchRule::Descendants,
+            _ => return Err(Error::InvalidRule(format!("unknown rule kind {}", kind))),
+        };
+
+        rule.validate()?;
+
+        Ok(rule)
+    }
+
+    /// Checks that the glob or regex compiles.
+    pub fn validate(&self) -> Result<()> {
+        self.compile().map(|_| ())
+    }
+
+    /// The rule with the file `from` swapped for `to`, for a game whose
+    /// executable moved. The path itself is replaced, otherwise its folder;
+    /// rules mentioning neither are returned as they are.
+    pub fn retarget(&self, from: &Path, to: &Path) -> MatchRule {
+        let name = |path: &Path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        };
+
+        let mut pairs = vec![(
+            from.to_string_lossy().into_owned(),
+            to.to_string_lossy().into_owned(),
+        )];
+
+        if let (Some(from), Some(to)) = (from.parent(), to.parent()) {
+            // with the separator, so /games/old doesn't hit /games/older
+            let dir =
+                |dir: &Path| format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR);
+
+            if !from.as_os_str().is_empty() && from != to {
+                pairs.push((dir(from), dir(to)));
+            }
+        }
+
+        let replace = |pattern: &str, escape: fn(&str) -> String| {
+            pairs
+                .iter()
+                .map(|(from, to)| (escape(from), escape(to)))
+                .find(|(from, _)| pattern.contains(from.as_str()))
+                .map(|(from, to)| pattern.replace(&from, &to))
+                .unwrap_or_else(|| pattern.to_string())
+        };
+
+        match self {
+            MatchRule::ExeName(value) => match (name(from), name(to)) {
+                (Some(from), Some(to)) if value.eq_ignore_ascii_case(&from) => {
+                    MatchRule::ExeName(to)
+                }
+                _ => self.clone(),
+            },
+            MatchRule::ExePath(pattern) => {
+                MatchRule::ExePath(replace(pattern, glob::Pattern::escape))
+            }
+            MatchRule::CommandLine(pattern) => {
+                MatchRule::CommandLine(replace(pattern, regex::escape))
+            }
+            MatchRule::Descendants => self.clone(),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MatchRule::ExeName(_) => "exe_name",
+            MatchRule::ExePath(_) => "exe_path",
+            MatchRule::CommandLine(_) => "command_line",
+            MatchRule::Descendants => "descendants",
+        }
+    }
+
+    fn compile(&self) -> Result<Compiled> {
+        Ok(match self {
+            MatchRule::ExeName(name) => Compiled::ExeName(name.to_lowercase()),
+            MatchRule::ExePath(pattern) => Compiled::ExePath(
+                glob::Pattern::new(pattern).map_err(|err| Error::InvalidRule(err.to_string()))?,
+            ),
+            MatchRule::CommandLine(pattern) => Compiled::CommandLine(
+                regex::Regex::new(pattern).map_err(|err| Error::InvalidRule(err.to_string()))?,
+            ),
+            MatchRule::Descendants => Compiled::Descendants,
+        })
+    }
+}
+
+/// `kind:value`, or just `descendants`.
+impl fmt::Display for MatchRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchRule::ExeName(value)
+            | MatchRule::ExePath(value)
+            | MatchRule::CommandLine(value) => {
+                write!(f, "{}:{}", self.kind(), value)
+            }
+            MatchRule::Descendants => write!(f, "{}", self.kind()),
+        }
+    }
+}
+
+impl FromStr for MatchRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, value) = s.split_once(':').unwrap_or((s, ""));
+
+        MatchRule::new(kind, value)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Compiled {
+    ExeName(String),
+    ExePath(glob::Pattern),
+    CommandLine(regex::Regex),
+    Descendants,
+    /// An argument equal to one of `values`, in a process running one of
+    /// `programs`, or any program if there are none. Only made for launch
+    /// targets, see `target_rules`.
+    Argument {
+        programs: Vec<String>,
+        values: Vec<String>,
+    },
+}
+
+/// What the scanner knows about one running process.
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub parent: Option<u32>,
+    /// Lowercase process name.
+    pub name: String,
+    pub exe: Option<PathBuf>,
+    /// Tracker key of the process itself, see `tracker::process_key`.
+    pub key: String,
+    pub cmd: String,
+    /// Command line arguments after the program itself.
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+struct GameRules {
+    key: String,
+    rules: Vec<Compiled>,
+}
+
+/// Compiled match rules of every game in the library.
+#[derive(Clone, Debug, Default)]
+pub struct Matcher {
+    games: Vec<GameRules>,
+}
+
+impl Matcher {
+    /// Rules that fail to compile are skipped; `Library::add` rejects them
+    /// so this only happens with hand-edited files.
+    pub fn new(library: &Library) -> Self {
+        let games = library
+            .games()
+            .iter()
+            .map(|game| GameRules {
+                key: game.key(),
+                rules: game
+                    .rules
+                    .iter()
+                    .filter_map(|rule| match rule.compile() {
+                        Ok(rule) => Some(rule),
+                        Err(err) => {
+                            log::warn!("Ignoring rule {} of {}: {}", rule, game.name, err);
+                            None
+                        }
+                    })
+                    .chain(target_rules(game))
+                    .collect(),
+            })
+            .collect();
+
+        Self { games }
+    }
+
+    /// The key of the game `process` belongs to, if any. `launched` is the
+    /// key of the game whose launched process tree contains it.
+    pub fn game_for(&self, process: &ProcessInfo, launched: Option<&str>) -> Option<&str> {
+        self.find(process, launched, false)
+    }
+
+    /// Like `game_for`, but leaving out command line rules that aren't
+    /// anchored with `^` and `$`, for what panic stops. Those can also hit
+    /// an editor or terminal that has the game's name on its command line.
+    pub fn stoppable_game_for(&self, process: &ProcessInfo) -> Option<&str> {
+        self.find(process, None, true)
+    }
+
+    fn find(&self, process: &ProcessInfo, launched: Option<&str>, strict: bool) -> Option<&str> {
+        self.games
+            .iter()
+            .find(|game| {
+                game.key == process.key
+                    || game.rules.iter().any(|rule| match rule {
+                        Compiled::ExeName(name) => process.name == *name,
+                        Compiled::ExePath(pattern) => process
+                            .exe
+                            .as_deref()
+                            .is_some_and(|exe| pattern.matches_path(exe)),
+                        Compiled::CommandLine(regex) if strict && !is_anchored(regex) => false,
+                        Compiled::CommandLine(regex) => regex.is_match(&process.cmd),
+                        Compiled::Descendants => launched == Some(game.key.as_str()),
+                        Compiled::Argument { programs, values } => {
+                            runs(process, programs)
+                                && process.args.iter().any(|arg| values.contains(arg))
+                        }
+                    })
+            })
+            .map(|game| game.key.as_str())
+    }
+}
+
+fn is_anchored(regex: &regex::Regex) -> bool {
+    regex.as_str().starts_with('^') && regex.as_str().ends_with('$')
+}
+
+/// How processes of games that aren't a plain executable are recognized,
+/// on top of their own rules. The file or id has to be a whole argument of
+/// the program that runs it, so an editor or file manager showing it isn't
+/// taken for the game.
+fn target_rules(game: &Game) -> Vec<Compiled> {
+    let argument = |programs: &[&str], values: Vec<String>| Compiled::Argument {
+        programs: programs.iter().map(|program| program.to_string()).collect(),
+        values,
+    };
+
+    match &game.location {
+        // run by an interpreter, java or wine, the process is that program
+        // and has their key instead, with the file on its command line
+        LaunchTarget::Executable(path) => match runners(path, game.wine.is_some()) {
+            Some(programs) => vec![
+                Compiled::Argument {
+                    programs,
+                    values: path_values(path),
+                },
+                Compiled::Descendants,
+            ],
+            None => Vec::new(),
+        },
+        LaunchTarget::Url(_) => Vec::new(),
+        // the shell and whatever it runs
+        LaunchTarget::Shell(_) => vec![Compiled::Descendants],
+        // `flatpak run` and the sandbox keep the app id on their command line
+        LaunchTarget::Flatpak(id) => vec![
+            argument(
+                &["flatpak", "bwrap"],
+                vec![id.clone(), format!("--app={}", id)],
+            ),
+            Compiled::Descendants,
+        ],
+        // steam starts games under a reaper with AppId=<id>
+        LaunchTarget::Steam(id) => vec![argument(&[], vec![format!("AppId={}", id)])],
+        LaunchTarget::Emulator { emulator, rom } => {
+            let programs: Vec<String> = emulator
+                .file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .into_iter()
+                .collect();
+
+            vec![Compiled::Argument {
+                programs,
+                values: path_values(rom),
+            }]
+        }
+    }
+}
+
+/// `path` as it was given and as it is on disk.
+fn path_values(path: &Path) -> Vec<String> {
+    let mut values = vec![path.to_string_lossy().into_owned()];
+    let key = process_key(path);
+
+    if !values.contains(&key) {
+        values.push(key);
+    }
+
+    values
+}
+
+/// The programs `path` runs as when it isn't run by itself, see
+/// `target_rules`.
+fn runners(path: &Path, wine: bool) -> Option<Vec<String>> {
+    const WINE: [&str; 5] = [
+        "wine",
+        "wine64",
+        "wine-preloader",
+        "wine64-preloader",
+        "proton",
+    ];
+
+    let programs = match detect_cached(path) {
+        _ if wine => WINE.iter().map(|program| program.to_string()).collect(),
+        Ok(FileType::Windows) if !cfg!(windows) => {
+            WINE.iter().map(|program| program.to_string()).collect()
+        }
+        Ok(FileType::Script(Some(words))) => words
+            .iter()
+            .filter_map(|word| Path::new(word).file_name())
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .collect(),
+        Ok(FileType::Script(None)) => vec!["sh", "dash", "bash", "python3"]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        Ok(FileType::Jar) => vec!["java".to_string()],
+        _ => return None,
+    };
+
+    Some(programs)
+}
+
+/// Whether `process` is one of `programs`, by name or executable. Any
+/// process is when `programs` is empty.
+fn runs(process: &ProcessInfo, programs: &[String]) -> bool {
+    let exe = process
+        .exe
+        .as_deref()
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy().to_lowercase());
+
+    programs.is_empty()
+        || programs
+            .iter()
+            .any(|program| *program == process.name || Some(program) == exe.as_ref())
+}
+
+/// Remembers which game launched each process tree, so descendants are
+/// still attributed after their parent exits.
+#[derive(Default)]
+pub struct Lineage {
+    owners: HashMap<u32, String>,
+}
+
+impl Lineage {
+    /// Records that `pid` was launched for the game with key `key`.
+    pub fn launched(&mut self, pid: u32, key: String) {
+        self.owners.insert(pid, key);
+    }
+
+    /// Adopts new children of known processes and forgets exited ones.
+    pub fn update(&mut self, processes: &[ProcessInfo]) {
+        let parents: HashMap<u32, u32> = processes
+            .iter()
+            .filter_map(|process| Some((process.pid, process.parent?)))
+            .collect();
+
+        for process in processes {
+            let mut chain = vec![process.pid];
+            let mut pid = process.pid;
+
+            // bounded in case of a cycle from pid reuse
+            while chain.len() < 64 && !self.owners.contains_key(&pid) {
+                match parents.get(&pid) {
+                    Some(parent) => {
+                        pid = *parent;
+                        chain.push(pid);
+                    }
+                    None => break,
+                }
+            }
+
+            if let Some(key) = self.owners.get(&pid).cloned() {
+                for pid in chain {
+                    self.owners.entry(pid).or_insert_with(|| key.clone());
+                }
+            }
+        }
+
+        let alive: HashSet<u32> = processes.iter().map(|process| process.pid).collect();
+
+        self.owners.retain(|pid, _| alive.contains(pid));
+    }
+
+    pub fn pids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.owners.keys().copied()
+    }
+
+    pub fn owner(&self, pid: u32) -> Option<&str> {
+        self.owners.get(&pid).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::{game, process, temp_dir};
+
+    #[test]
+    fn scripts_match_their_interpreter() {
+        let dir = temp_dir("matching");
+        let script = dir.join("start.sh");
+
+        std::fs::write(&script, "#!/bin/sh\nexec ./game\n").unwrap();
+
+        let mut library = Library::default();
+
+        library
+            .add(game("Script", LaunchTarget::Executable(script.clone())))
+            .unwrap();
+
+        let key = library.games()[0].key();
+        let matcher = Matcher::new(&library);
+
+        // named after the #! line, /bin/sh links to dash
+        let mut started = process(
+            1,
+            None,
+            "/usr/bin/dash",
+            &format!("/bin/sh {}", script.display()),
+        );
+
+        started.name = "sh".to_string();
+
+        let child = process(2, None, "/games/game", "./game");
+        let other = process(3, None, "/usr/bin/dash", "/bin/sh other.sh");
+        let editor = process(
+            4,
+            None,
+            "/usr/bin/vim",
+            &format!("vim {}", script.display()),
+        );
+        let folder = process(
+            5,
+            None,
+            "/usr/bin/nautilus",
+            &format!("nautilus {}", dir.display()),
+        );
+
+        assert_eq!(matcher.game_for(&started, None), Some(key.as_str()));
+        assert_eq!(matcher.game_for(&child, Some(&key)), Some(key.as_str()));
+        assert_eq!(matcher.game_for(&other, None), None);
+        assert_eq!(matcher.game_for(&editor, None), None);
+        assert_eq!(matcher.game_for(&folder, None), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn app_ids_match_whole_arguments() {
+        let mut library = Library::default();
+
+        library
+            .add(game(
+                "Flatpak",
+                LaunchTarget::Flatpak("com.foo.Game".to_string()),
+            ))
+            .unwrap();
+
+        let matcher = Matcher::new(&library);
+
+        let run = process(1, None, "/usr/bin/flatpak", "flatpak run com.foo.Game");
+        let tools = process(2, None, "/usr/bin/flatpak", "flatpak run com.foo.GameTools");
+        let editor = process(3, None, "/usr/bin/vim", "vim com.foo.Game");
+
+        assert_eq!(matcher.game_for(&run, None), Some("flatpak:com.foo.Game"));
+        assert_eq!(matcher.game_for(&tools, None), None);
+        assert_eq!(matcher.game_for(&editor, None), None);
+    }
+}
@@ -0,0 +1,31 @@
+//! UI-free launcher core: the game library, playtime tracker, launcher and
+//! panic routine. The egui frontend in `app.rs` only calls into this module.
+
+pub mod budget;
+pub mod clock;
+pub mod control;
+pub mod data;
+pub mod detect;
+pub mod error;
+#[cfg(test)]
+pub mod fixtures;
+pub mod format;
+pub mod launcher;
+pub mod library;
+pub mod lock;
+pub mod matching;
+pub mod panic;
+pub mod process;
+pub mod restore;
+pub mod runner;
+pub mod scope;
+pub mod session;
+pub mod settings;
+pub mod store;
+pub mod target;
+pub mod tracker;
+pub mod triggers;
+pub mod wrappers;
+
+pub use crate::structs::Game;
+pub use error::{Error, Result};
@@ -0,0 +1,448 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::core::launcher::Launcher;
+use crate::core::library::Library;
+use crate::core::matching::{Matcher, ProcessInfo};
+use crate::core::process::{self, Signal};
+use crate::core::restore::Snapshot;
+use crate::core::settings::{PanicMode, Settings};
+use crate::core::tracker::Scanner;
+use crate::core::{Error, Result};
+
+/// A process ended by `kill_games`, or signalled by `suspend_games` and
+/// `resume_games`.
+#[derive(Clone, Debug)]
+pub struct Killed {
+    pub pid: u32,
+    pub name: String,
+    /// Still running after the grace period, so it was sent SIGKILL.
+    pub forced: bool,
+}
+
+impl fmt::Display for Killed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.pid)?;
+
+        if self.forced {
+            write!(f, ", forced")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Ends every game process tree among `processes`, see `targets`.
+/// Everything gets SIGTERM first, whatever is left after `grace` gets
+/// SIGKILL. Does not exit the current process. `launcher` is only locked
+/// briefly, not while waiting.
+pub fn kill_games(
+    launcher: &Mutex<Launcher>,
+    library: &Library,
+    processes: &[ProcessInfo],
+    tracked: &[u32],
+    grace: Duration,
+) -> Vec<Killed> {
+    let groups = launcher.lock().unwrap().pids();
+    let targets = targets(processes, groups.clone(), library, tracked);
+
+    kill(launcher, targets, groups, grace)
+}
+
+/// Like `kill_games` for the game with id `id` alone: its launched copies,
+/// the processes it matches and their descendants.
+pub fn kill_game(
+    launcher: &Mutex<Launcher>,
+    library: &Library,
+    processes: &[ProcessInfo],
+    id: Uuid,
+    grace: Duration,
+) -> Vec<Killed> {
+    let groups = launcher.lock().unwrap().game_pids(id);
+
+    let mut roots = groups.clone();
+
+    if let Some(game) = library.find(id) {
+        let matcher = Matcher::new(library);
+        let key = game.key();
+
+        roots.extend(
+            processes
+                .iter()
+                .filter(|process| matcher.stoppable_game_for(process) == Some(key.as_str()))
+                .map(|process| process.pid),
+        );
+    }
+
+    let targets = with_descendants(processes, roots);
+
+    kill(launcher, targets, groups, grace)
+}
+
+/// Sends SIGTERM to `targets` and the process `groups`, then SIGKILL to
+/// whatever is left after `grace`.
+fn kill(
+    launcher: &Mutex<Launcher>,
+    targets: HashMap<u32, String>,
+    groups: Vec<u32>,
+    grace: Duration,
+) -> Vec<Killed> {
+    // the launched games lead their own process group, which also holds
+    // children that were started after the scan
+    for pid in &groups {
+        process::send_group(*pid, Signal::Term);
+
+        // a suspended game can't handle SIGTERM until it runs again, there is
+        // nothing to continue elsewhere
+        if cfg!(unix) {
+            process::send_group(*pid, Signal::Cont);
+        }
+    }
+
+    for pid in targets.keys() {
+        process::send(*pid, Signal::Term);
+
+        if cfg!(unix) {
+            process::send(*pid, Signal::Cont);
+        }
+    }
+
+    let deadline = Instant::now() + grace;
+    let mut alive: Vec<u32> = targets.keys().copied().collect();
+
+    loop {
+        launcher.lock().unwrap().reap();
+
+        alive = process::alive(&alive);
+
+        if alive.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    if !alive.is_empty() {
+        for pid in &groups {
+            process::send_group(*pid, Signal::Kill);
+        }
+
+        for pid in &alive {
+            if !process::send(*pid, Signal::Kill) {
+                log::warn!("Could not kill {} ({})", targets[pid], pid);
+            }
+        }
+    }
+
+    launcher.lock().unwrap().reap();
+
+    let mut killed: Vec<Killed> = targets
+        .into_iter()
+        .map(|(pid, name)| Killed {
+            pid,
+            name,
+            forced: alive.contains(&pid),
+        })
+        .collect();
+
+    killed.sort_by(|a, b| a.name.cmp(&b.name).then(a.pid.cmp(&b.pid)));
+
+    killed
+}
+
+/// Freezes every game process tree among `processes`, see `targets`.
+/// Returns the frozen processes.
+pub fn suspend_games(
+    launcher: &Launcher,
+    library: &Library,
+    processes: &[ProcessInfo],
+    tracked: &[u32],
+) -> Vec<Killed> {
+    signal_games(launcher, library, processes, tracked, Signal::Stop)
+}
+
+/// Undoes `suspend_games`. Works after a restart too, as long as the games
+/// are in the library or their processes match its rules.
+pub fn resume_games(
+    launcher: &Launcher,
+    library: &Library,
+    processes: &[ProcessInfo],
+    tracked: &[u32],
+) -> Vec<Killed> {
+    signal_games(launcher, library, processes, tracked, Signal::Cont)
+}
+
+fn signal_games(
+    launcher: &Launcher,
+    library: &Library,
+    processes: &[ProcessInfo],
+    tracked: &[u32],
+    signal: Signal,
+) -> Vec<Killed> {
+    for pid in launcher.pids() {
+        process::send_group(pid, signal);
+    }
+
+    let mut signalled: Vec<Killed> = targets(processes, launcher.pids(), library, tracked)
+        .into_iter()
+        .filter(|(pid, _)| process::send(*pid, signal))
+        .map(|(pid, name)| Killed {
+            pid,
+            name,
+            forced: false,
+        })
+        .collect();
+
+    signalled.sort_by(|a, b| a.name.cmp(&b.name).then(a.pid.cmp(&b.pid)));
+
+    signalled
+}
+
+/// The game processes panic acts on: the `launched` children of the
+/// launcher, the `tracked` pids (processes the tracker attributed to a
+/// launch) and every one of `processes` a game in `library` surely matches,
+/// see `Matcher::stoppable_game_for`, plus all of their descendants.
+fn targets(
+    processes: &[ProcessInfo],
+    launched: Vec<u32>,
+    library: &Library,
+    tracked: &[u32],
+) -> HashMap<u32, String> {
+    let mut roots = launched;
+
+    roots.extend(tracked);
+
+    matching_targets(processes, library, roots)
+}
+
+/// `roots` and the processes of `library` among `processes`, with their
+/// descendants.
+fn matching_targets(
+    processes: &[ProcessInfo],
+    library: &Library,
+    mut roots: Vec<u32>,
+) -> HashMap<u32, String> {
+    let matcher = Matcher::new(library);
+
+    roots.extend(
+        processes
+            .iter()
+            .filter(|process| matcher.stoppable_game_for(process).is_some())
+            .map(|process| process.pid),
+    );
+
+    with_descendants(processes, roots)
+}
+
+/// `roots` and every process below them, by pid with their names. The
+/// launcher and the processes it runs under, like the shell that started
+/// `gamelunch panic`, are never included.
+fn with_descendants(processes: &[ProcessInfo], roots: Vec<u32>) -> HashMap<u32, String> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut parents: HashMap<u32, u32> = HashMap::new();
+
+    for process in processes {
+        if let Some(parent) = process.parent {
+            children.entry(parent).or_default().push(process.pid);
+            parents.insert(process.pid, parent);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut pid = std::process::id();
+
+    // marked seen up front so they are skipped
+    while seen.insert(pid) {
+        match parents.get(&pid) {
+            Some(parent) => pid = *parent,
+            None => break,
+        }
+    }
+
+    let ancestors = seen.clone();
+
+    let names: HashMap<u32, &str> = processes
+        .iter()
+        .map(|process| (process.pid, process.name.as_str()))
+        .collect();
+
+    let mut queue = roots;
+
+    while let Some(pid) = queue.pop() {
+        if !names.contains_key(&pid) || !seen.insert(pid) {
+            continue;
+        }
+
+        queue.extend(children.get(&pid).into_iter().flatten());
+    }
+
+    seen.difference(&ancestors)
+        .map(|pid| (*pid, names[pid].to_string()))
+        .collect()
+}
+
+/// What pressing PANIC did.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// The killed processes, and the games that were running.
+    Killed(Vec<Killed>, Snapshot),
+    Suspended(Vec<Killed>),
+    Hidden,
+    Decoy(u32),
+}
+
+impl Outcome {
+    /// The games to offer restoring, if the panic stopped any. A second
+    /// panic with nothing running keeps the first set.
+    pub fn restore(&self) -> Option<&Snapshot> {
+        match self {
+            Outcome::Killed(_, snapshot) if !snapshot.games.is_empty() => Some(snapshot),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Killed(killed, _) => write!(f, "Killed {} processes", killed.len()),
+            Outcome::Suspended(suspended) => {
+                write!(f, "Suspended {} processes", suspended.len())
+            }
+            Outcome::Hidden => write!(f, "Games left running"),
+            Outcome::Decoy(pid) => write!(f, "Opened decoy ({})", pid),
+        }
+    }
+}
+
+/// The PANIC button: does whatever `settings.panic_mode` says to the
+/// games. Hiding or closing the launcher is up to the caller.
+pub fn panic(
+    launcher: &Mutex<Launcher>,
+    library: &Library,
+    tracked: &[u32],
+    settings: &Settings,
+) -> Result<Outcome> {
+    let outcome = match settings.panic_mode {
+        PanicMode::Kill => {
+            // one scan for both, a panic should not wait on the process list
+            let processes = Scanner::default().scan();
+            let snapshot = Snapshot::capture(library, &mut launcher.lock().unwrap(), &processes);
+
+            let killed = kill_games(
+                launcher,
+                library,
+                &processes,
+                tracked,
+                Duration::from_secs(settings.panic_grace_secs),
+            );
+
+            Outcome::Killed(killed, snapshot)
+        }
+        PanicMode::Suspend => {
+            let processes = Scanner::default().scan();
+
+            Outcome::Suspended(suspend_games(
+                &launcher.lock().unwrap(),
+                library,
+                &processes,
+                tracked,
+            ))
+        }
+        PanicMode::Hide => Outcome::Hidden,
+        PanicMode::Decoy => {
+            if settings.decoy.trim().is_empty() {
+                return Err(Error::MissingDecoy);
+            }
+
+            Outcome::Decoy(
+                launcher
+                    .lock()
+                    .unwrap()
+                    .open(Path::new(settings.decoy.trim()))?,
+            )
+        }
+    };
+
+    if let Outcome::Killed(processes, _) | Outcome::Suspended(processes) = &outcome {
+        for process in processes {
+            log::info!("{:?}: {}", settings.panic_mode, process);
+        }
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::{game, process};
+    use crate::core::matching::MatchRule;
+    use crate::core::target::LaunchTarget;
+    use crate::core::Game;
+
+    // above the largest pid Linux hands out, so none is a real process
+    const PID: u32 = 10_000_000;
+
+    #[test]
+    fn targets_leave_other_programs_alone() {
+        let mut library = Library::default();
+
+        library
+            .add(Game {
+                rules: vec![
+                    MatchRule::ExeName("celeste".to_string()),
+                    MatchRule::CommandLine("Celeste".to_string()),
+                    MatchRule::CommandLine("^/opt/celeste/run --windowed$".to_string()),
+                ],
+                ..game("Celeste", LaunchTarget::Steam(504230))
+            })
+            .unwrap();
+
+        let processes = [
+            process(PID + 1, None, "celeste", "/games/Celeste/celeste"),
+            process(PID + 2, Some(PID + 1), "crashhandler", "crashhandler"),
+            process(PID + 3, Some(PID + 2), "minidump", "minidump"),
+            process(PID + 4, None, "vim", "vim /games/Celeste/notes.txt"),
+            process(PID + 5, None, "run", "/opt/celeste/run --windowed"),
+            process(PID + 6, None, "launched", "launched"),
+            process(PID + 7, Some(PID + 6), "child", "child"),
+            process(PID + 8, None, "bash", "bash"),
+        ];
+
+        let targets = matching_targets(&processes, &library, vec![PID + 6]);
+        let mut pids: Vec<u32> = targets.keys().map(|pid| pid - PID).collect();
+
+        pids.sort_unstable();
+
+        assert_eq!(pids, [1, 2, 3, 5, 6, 7]);
+        assert_eq!(targets[&(PID + 2)], "crashhandler");
+
+        // still tracked as the game, only not stopped
+        assert!(Matcher::new(&library)
+            .game_for(&processes[3], None)
+            .is_some());
+    }
+
+    #[test]
+    fn descendants_skip_the_launcher() {
+        let own = std::process::id();
+
+        let processes = [
+            process(PID + 1, None, "shell", "sh"),
+            process(own, Some(PID + 1), "gamelunch", "gamelunch panic"),
+            process(PID + 2, Some(PID + 1), "game", "game"),
+        ];
+
+        // the shell that ran `gamelunch panic` started the game too
+        let targets = with_descendants(&processes, vec![PID + 1, PID + 2]);
+
+        assert!(!targets.contains_key(&own) && !targets.contains_key(&(PID + 1)));
+        assert!(targets.contains_key(&(PID + 2)));
+    }
+}
@@ -0,0 +1,103 @@
+use sysinfo::{Pid, ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System};
+
+/// What to do to a process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// Ask it to exit.
+    Term,
+    /// Kill it outright.
+    Kill,
+    /// Freeze it.
+    Stop,
+    /// Resume it after `Stop`.
+    Cont,
+}
+
+/// Sends `signal` to `pid`. Returns whether it was delivered.
+#[cfg(unix)]
+pub fn send(pid: u32, signal: Signal) -> bool {
+    // 0 would signal our own process group
+    if pid == 0 || pid == std::process::id() {
+        return false;
+    }
+
+    // SAFETY: kill only takes plain integers
+    unsafe { libc::kill(pid as libc::pid_t, raw(signal)) == 0 }
+}
+
+/// Sends `signal` to every process in the group led by `pid`, which
+/// `Launcher::launch` gives each game.
+#[cfg(unix)]
+pub fn send_group(pid: u32, signal: Signal) -> bool {
+    if pid == 0 || pid == std::process::id() {
+        return false;
+    }
+
+    // SAFETY: as above, a negative pid addresses the process group
+    unsafe { libc::kill(-(pid as libc::pid_t), raw(signal)) == 0 }
+}
+
+#[cfg(unix)]
+fn raw(signal: Signal) -> libc::c_int {
+    match signal {
+        Signal::Term => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+        Signal::Stop => libc::SIGSTOP,
+        Signal::Cont => libc::SIGCONT,
+    }
+}
+
+/// Windows has no signals: `Term` asks the windows of the process to
+/// close, `Kill` terminates it. Suspending isn't supported.
+#[cfg(not(unix))]
+pub fn send(pid: u32, signal: Signal) -> bool {
+    if pid == 0 || pid == std::process::id() {
+        return false;
+    }
+
+    let mut command = std::process::Command::new("taskkill");
+
+    match signal {
+        Signal::Term => {}
+        Signal::Kill => {
+            command.arg("/F");
+        }
+        Signal::Stop | Signal::Cont => {
+            log::warn!("Suspending processes is not supported on this platform");
+
+            return false;
+        }
+    }
+
+    command
+        .args(["/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Process groups can't be signalled on Windows, callers send to every
+/// member of the tree anyway.
+#[cfg(not(unix))]
+pub fn send_group(pid: u32, signal: Signal) -> bool {
+    send(pid, signal)
+}
+
+/// The pids in `pids` that are still running. Zombies count as exited.
+pub fn alive(pids: &[u32]) -> Vec<u32> {
+    let mut system = System::new();
+
+    let list: Vec<Pid> = pids.iter().map(|pid| Pid::from_u32(*pid)).collect();
+
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&list), ProcessRefreshKind::new());
+
+    pids.iter()
+        .copied()
+        .filter(|pid| {
+            system
+                .process(Pid::from_u32(*pid))
+                .is_some_and(|process| process.status() != ProcessStatus::Zombie)
+        })
+        .collect()
+}
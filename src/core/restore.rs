@@ -0,0 +1,229 @@
+use uuid::Uuid;
+
+use crate::core::budget::Budgets;
+use crate::core::launcher::Launcher;
+use crate::core::library::Library;
+use crate::core::matching::{Matcher, ProcessInfo};
+use crate::core::session::History;
+use crate::core::{Error, Game, Result};
+
+/// A game that was running when panic fired. It is started the way the
+/// library has it when restored, so edits made since apply.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct RunningGame {
+    #[serde(default)]
+    pub id: Uuid,
+    /// Only for telling which game was removed since.
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// The games running when panic last killed everything, so they can be
+/// started again afterwards.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Default)]
+pub struct Snapshot {
+    /// Unix timestamp of the panic.
+    pub time: u64,
+    pub games: Vec<RunningGame>,
+}
+
+impl Snapshot {
+    /// Records which library games are in `processes`. Arguments come from
+    /// the launcher for games it started, otherwise from the game's own
+    /// process; games only found through their rules get none.
+    pub fn capture(library: &Library, launcher: &mut Launcher, processes: &[ProcessInfo]) -> Self {
+        let matcher = Matcher::new(library);
+
+        launcher.reap();
+
+        let games = library
+            .games()
+            .iter()
+            .filter_map(|game| {
+                let key = game.key();
+
+                let args = match launcher.args(game.id) {
+                    Some(args) => args.to_vec(),
+                    None => match processes.iter().find(|process| process.key == key) {
+                        Some(process) => process.args.clone(),
+                        None => {
+                            processes.iter().find(|process| {
+                                matcher.game_for(process, None) == Some(key.as_str())
+                            })?;
+
+                            Vec::new()
+                        }
+                    },
+                };
+
+                Some(RunningGame {
+                    id: game.id,
+                    name: game.name.clone(),
+                    args,
+                })
+            })
+            .collect();
+
+        Self {
+            time: crate::core::session::now(),
+            games,
+        }
+    }
+
+    /// Starts every recorded game again with its arguments at `now`, and
+    /// keeps those that failed to start to try again later. Games removed
+    /// from the library since are reported as not found and dropped, and
+    /// games whose budget ran out are refused like any launch.
+    pub fn relaunch(
+        &mut self,
+        launcher: &mut Launcher,
+        library: &Library,
+        budgets: &Budgets,
+        history: &History,
+        now: u64,
+    ) -> Vec<Result<(Game, Option<u32>)>> {
+        let mut results = Vec::new();
+
+        self.games.retain(|running| {
+            let result = library
+                .find(running.id)
+                .ok_or_else(|| Error::GameNotFound(running.name.clone()))
+                .and_then(|game| {
+                    budgets.allow(history, game.id, now)?;
+
+                    let pid = launcher.launch_with(game, &running.args)?;
+
+                    Ok((game.clone(), pid))
+                });
+
+            let keep = matches!(&result, Err(err) if !matches!(err, Error::GameNotFound(_)));
+
+            results.push(result);
+
+            keep
+        });
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::budget::Budget;
+    use crate::core::fixtures::{game, process};
+    use crate::core::matching::MatchRule;
+    use crate::core::session::day_start;
+    use crate::core::settings::Settings;
+    use crate::core::target::LaunchTarget;
+
+    #[test]
+    fn captures_running_games() {
+        let mut library = Library::default();
+        let mut wrapped = game("Wrapped", LaunchTarget::Steam(2));
+
+        wrapped.rules = vec![MatchRule::ExeName("wrapped".to_string())];
+
+        for game in [
+            game("Running", LaunchTarget::Steam(1)),
+            wrapped,
+            game("Closed", LaunchTarget::Steam(3)),
+        ] {
+            library.add(game).unwrap();
+        }
+
+        let processes = [
+            process(10, None, "steam://rungameid/1", "running --fast"),
+            process(11, None, "/games/wrapped", "/games/wrapped --slow"),
+            process(12, None, "/usr/bin/firefox", "firefox"),
+        ];
+
+        let snapshot = Snapshot::capture(&library, &mut Launcher::default(), &processes);
+        let running: Vec<(&str, Vec<String>)> = snapshot
+            .games
+            .iter()
+            .map(|running| (running.name.as_str(), running.args.clone()))
+            .collect();
+
+        // only the game's own process says which arguments it got
+        assert_eq!(
+            running,
+            [
+                ("Running", vec!["--fast".to_string()]),
+                ("Wrapped", Vec::new())
+            ]
+        );
+        assert_eq!(snapshot.games[0].id, library.get("Running").unwrap().id);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relaunch_keeps_what_failed() {
+        let mut library = Library::default();
+        let mut limited = game("Limited", LaunchTarget::Shell("true".to_string()));
+
+        limited.budget = Budget {
+            daily_minutes: Some(1),
+            weekly_minutes: None,
+        };
+
+        for game in [
+            game("Edited", LaunchTarget::Shell("exit 1".to_string())),
+            limited,
+            game("Removed", LaunchTarget::Steam(1)),
+        ] {
+            library.add(game).unwrap();
+        }
+
+        let id = |name: &str| library.get(name).unwrap().id;
+        let mut snapshot = Snapshot {
+            time: 0,
+            games: ["Edited", "Limited", "Removed"]
+                .into_iter()
+                .map(|name| RunningGame {
+                    id: id(name),
+                    name: name.to_string(),
+                    args: vec!["--fast".to_string()],
+                })
+                .collect(),
+        };
+
+        let limited = library.get("Limited").unwrap().clone();
+        let now = day_start(chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()) + 12 * 60 * 60;
+        let mut history = History::default();
+
+        history.record_for_test(&limited.key(), now - 120, 60);
+
+        // edits made after the panic apply
+        let edited = id("Edited");
+        library
+            .edit(
+                edited,
+                game("Edited", LaunchTarget::Shell("exit 0".to_string())),
+            )
+            .unwrap();
+        library
+            .remove_by_name("Removed", &mut History::default(), now)
+            .unwrap();
+
+        let budgets = Budgets::new(&Settings::default(), &library);
+        let mut launcher = Launcher::default();
+
+        let results = snapshot.relaunch(&mut launcher, &library, &budgets, &history, now);
+
+        assert!(
+            matches!(&results[0], Ok((game, _)) if game.location == LaunchTarget::Shell("exit 0".to_string()))
+        );
+        assert_eq!(
+            launcher.args(edited),
+            Some(["--fast".to_string()].as_slice())
+        );
+        assert!(matches!(results[1], Err(Error::OverBudget(_))));
+        assert!(matches!(results[2], Err(Error::GameNotFound(_))));
+
+        // the game over its budget is kept to try again, the removed one
+        // can never start
+        assert_eq!(snapshot.games.len(), 1);
+        assert_eq!(snapshot.games[0].id, limited.id);
+    }
+}
@@ -0,0 +1,307 @@
+//! Running Windows games through Wine or Proton. Each game gets its own
+//! prefix, by default under `prefixes/` in the data directory, so their
+//! settings and saves don't mix.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::core::data;
+use crate::core::{Error, Game, Result};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RunnerKind {
+    /// A `wine` binary, started as `wine game.exe`.
+    #[default]
+    Wine,
+    /// The `proton` script of a Proton build, started as
+    /// `proton run game.exe`.
+    Proton,
+}
+
+impl RunnerKind {
+    pub const ALL: [RunnerKind; 2] = [RunnerKind::Wine, RunnerKind::Proton];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RunnerKind::Wine => "Wine",
+            RunnerKind::Proton => "Proton",
+        }
+    }
+}
+
+/// A Wine or Proton build set up in the settings.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Runner {
+    pub name: String,
+    pub kind: RunnerKind,
+    pub path: PathBuf,
+}
+
+/// How a game is run through a runner.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq, Default)]
+pub struct WineConfig {
+    /// Name of a runner in `Settings::runners`.
+    pub runner: String,
+    /// The prefix, see `prefix`.
+    #[serde(default)]
+    pub prefix: Option<PathBuf>,
+    /// Variables for this prefix, like `DXVK_HUD`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Common prefix variables, as label, key and value.
+pub const TOGGLES: &[(&str, &str, &str)] = &[
+    ("DXVK HUD", "DXVK_HUD", "fps"),
+    ("Use WineD3D instead of DXVK", "PROTON_USE_WINED3D", "1"),
+    ("Esync", "WINEESYNC", "1"),
+    ("Fsync", "WINEFSYNC", "1"),
+    ("Large address aware", "WINE_LARGE_ADDRESS_AWARE", "1"),
+];
+
+impl Runner {
+    pub fn validate(&self, others: &[Runner]) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidRunner("a runner requires a name".to_string()));
+        }
+
+        if !self.path.is_file() {
+            return Err(Error::InvalidRunner(format!(
+                "{} does not exist",
+                self.path.display()
+            )));
+        }
+
+        if others.iter().any(|other| other.name == self.name) {
+            return Err(Error::InvalidRunner(format!(
+                "{} already exists",
+                self.name
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// The words before the game's executable and the variables pointing
+    /// the runner at `prefix`.
+    pub fn command(&self, prefix: &Path) -> (Vec<String>, BTreeMap<String, String>) {
+        let runner = self.path.to_string_lossy().into_owned();
+        let prefix = prefix.to_string_lossy().into_owned();
+
+        match self.kind {
+            RunnerKind::Wine => (
+                vec![runner],
+                BTreeMap::from([("WINEPREFIX".to_string(), prefix)]),
+            ),
+            RunnerKind::Proton => {
+                // proton refuses to start without a steam install, any
+                // folder will do outside of steam
+                let client = dirs::home_dir()
+                    .map(|home| home.join(".steam/steam"))
+                    .filter(|dir| dir.is_dir())
+                    .map(|dir| dir.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| prefix.clone());
+
+                (
+                    vec![runner, "run".to_string()],
+                    BTreeMap::from([
+                        ("STEAM_COMPAT_DATA_PATH".to_string(), prefix),
+                        ("STEAM_COMPAT_CLIENT_INSTALL_PATH".to_string(), client),
+                    ]),
+                )
+            }
+        }
+    }
+
+    /// Sets up a new prefix at `prefix` and waits for it to finish.
+    pub fn create_prefix(&self, prefix: &Path) -> Result<()> {
+        std::fs::create_dir_all(prefix)?;
+
+        let (mut words, env) = self.command(prefix);
+
+        words.extend(["wineboot".to_string(), "--init".to_string()]);
+
+        let status = Command::new(&words[0])
+            .args(&words[1..])
+            .envs(env)
+            .stdin(Stdio::null())
+            .status()
+            .map_err(|err| Error::Launch(self.path.clone(), err))?;
+
+        match status.success() {
+            true => Ok(()),
+            false => Err(Error::Prefix(format!(
+                "{} exited with {}",
+                self.name, status
+            ))),
+        }
+    }
+}
+
+/// Looks up the runner of `game` in `runners`, `None` for native games.
+pub fn resolve<'a>(runners: &'a [Runner], game: &Game) -> Result<Option<&'a Runner>> {
+    let Some(wine) = &game.wine else {
+        return Ok(None);
+    };
+
+    runners
+        .iter()
+        .find(|runner| runner.name == wine.runner)
+        .map(Some)
+        .ok_or_else(|| Error::UnknownRunner(wine.runner.clone()))
+}
+
+/// The prefix of `game`: the one it sets, or its own folder under
+/// `prefixes/` in the data directory, named by its id so renaming the game
+/// keeps it.
+pub fn prefix(game: &Game) -> Option<PathBuf> {
+    if let Some(prefix) = game.wine.as_ref().and_then(|wine| wine.prefix.clone()) {
+        return Some(prefix);
+    }
+
+    data::data_dir().map(|dir| dir.join("prefixes").join(game.id.to_string()))
+}
+
+/// Deletes the prefix at `prefix`. Refuses folders that don't look like a
+/// Wine or Proton prefix, in case a game was pointed at the wrong one.
+pub fn delete_prefix(prefix: &Path) -> Result<()> {
+    if !prefix.exists() {
+        return Ok(());
+    }
+
+    let is_prefix = prefix.join("system.reg").is_file() || prefix.join("pfx").is_dir();
+    let is_empty = prefix.read_dir()?.next().is_none();
+
+    if !is_prefix && !is_empty {
+        return Err(Error::Prefix(format!(
+            "{} is not a Wine prefix",
+            prefix.display()
+        )));
+    }
+
+    std::fs::remove_dir_all(prefix)?;
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::{game, temp_dir};
+    use crate::core::launcher::Launcher;
+    use crate::core::settings::Settings;
+    use crate::core::target::LaunchTarget;
+
+    /// A runner that records what it was asked to do in `log` instead of
+    /// running anything.
+    fn stub(dir: &Path, kind: RunnerKind) -> Runner {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("runner");
+        let script = format!(
+            "#!/bin/sh\necho \"$WINEPREFIX$STEAM_COMPAT_DATA_PATH $*\" >> {}\n",
+            dir.join("log").display()
+        );
+
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        Runner {
+            name: "stub".to_string(),
+            kind,
+            path,
+        }
+    }
+
+    #[test]
+    fn creates_and_deletes_prefixes() {
+        let dir = temp_dir("runner-prefix");
+        let runner = stub(&dir, RunnerKind::Wine);
+        let prefix = dir.join("prefix");
+
+        runner.create_prefix(&prefix).unwrap();
+
+        let log = std::fs::read_to_string(dir.join("log")).unwrap();
+
+        assert_eq!(log.trim(), format!("{} wineboot --init", prefix.display()));
+
+        // wineboot would have made these
+        std::fs::write(prefix.join("system.reg"), "").unwrap();
+
+        delete_prefix(&prefix).unwrap();
+
+        assert!(!prefix.exists());
+
+        // not a prefix, left alone
+        assert!(delete_prefix(&dir).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn games_run_through_the_runner() {
+        let dir = temp_dir("runner-launch");
+        let exe = dir.join("game.exe");
+
+        std::fs::write(&exe, b"MZ\x90\x00").unwrap();
+
+        for (kind, words) in [(RunnerKind::Wine, 1), (RunnerKind::Proton, 2)] {
+            let runner = stub(&dir, kind);
+            let prefix = dir.join("prefix");
+
+            let game = Game {
+                wine: Some(WineConfig {
+                    runner: runner.name.clone(),
+                    prefix: Some(prefix.clone()),
+                    env: BTreeMap::from([("DXVK_HUD".to_string(), "fps".to_string())]),
+                }),
+                ..game("Windows", LaunchTarget::Executable(exe.clone()))
+            };
+
+            let mut launcher = Launcher::default();
+
+            launcher.configure(&Settings {
+                runners: vec![runner.clone()],
+                ..Default::default()
+            });
+
+            let line = launcher
+                .command_line(&game, &["-windowed".to_string()])
+                .unwrap();
+
+            assert_eq!(line.program, runner.path);
+            assert_eq!(
+                line.args[words - 1..],
+                [exe.display().to_string(), "-windowed".to_string()]
+            );
+            assert_eq!(line.env["DXVK_HUD"].as_deref(), Some("fps"));
+
+            let (_, env) = runner.command(&prefix);
+
+            for (key, value) in env {
+                assert_eq!(line.env[&key], Some(value));
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prefixes_are_named_by_id() {
+        let game = Game {
+            id: uuid::Uuid::new_v4(),
+            name: "Windows".to_string(),
+            wine: Some(WineConfig::default()),
+            ..Default::default()
+        };
+
+        let prefix = prefix(&game).unwrap();
+
+        assert!(prefix.ends_with(Path::new("prefixes").join(game.id.to_string())));
+
+        // only a path, nothing is made or moved until it's launched
+        assert!(!prefix.exists());
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::core::library::Library;
+use crate::core::matching::{Matcher, ProcessInfo};
+use crate::core::settings::{Settings, TrackingScope};
+use crate::core::tracker::process_name;
+use crate::core::Game;
+
+/// System, kernel and desktop processes skipped in `TrackingScope::All`.
+/// A trailing `*` matches any suffix.
+pub const DENYLIST: &[&str] = &[
+    // linux kernel threads
+    "kworker*",
+    "ksoftirqd*",
+    "kthreadd",
+    "kswapd*",
+    "kcompactd*",
+    "khugepaged",
+    "kauditd",
+    "kdevtmpfs",
+    "khungtaskd",
+    "kblockd",
+    "migration*",
+    "rcu_*",
+    "cpuhp*",
+    "idle_inject*",
+    "irq/*",
+    "watchdog*",
+    "oom_reaper",
+    "jbd2*",
+    "scsi_*",
+    // linux userspace
+    "init",
+    "systemd*",
+    "dbus-daemon",
+    "dbus-broker*",
+    "polkitd",
+    "udisksd",
+    "upowerd",
+    "networkmanager",
+    "wpa_supplicant",
+    "rtkit-daemon",
+    "pipewire*",
+    "pulseaudio",
+    "wireplumber",
+    "xorg",
+    "xwayland",
+    "sshd",
+    "cron",
+    "agetty",
+    "login",
+    "sudo",
+    "bash",
+    "zsh",
+    "fish",
+    "sh",
+    "dash",
+    "gvfs*",
+    "at-spi*",
+    "xdg-*",
+    // macos
+    "kernel_task",
+    "launchd",
+    "windowserver",
+    "mds*",
+    "loginwindow",
+    // windows
+    "system",
+    "registry",
+    "smss.exe",
+    "csrss.exe",
+    "wininit.exe",
+    "winlogon.exe",
+    "services.exe",
+    "lsass.exe",
+    "svchost.exe",
+    "dwm.exe",
+    "conhost.exe",
+    "runtimebroker.exe",
+    "searchindexer.exe",
+];
+
+pub fn is_denied(process: &str) -> bool {
+    DENYLIST
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => process.starts_with(prefix),
+            None => process == *pattern,
+        })
+}
+
+/// Decides which processes the tracker records and under which key, from
+/// the settings and the games currently in the library.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    scope: TrackingScope,
+    matcher: Matcher,
+    games: HashSet<String>,
+    allowlist: HashSet<String>,
+    use_denylist: bool,
+}
+
+impl Filter {
+    pub fn new(settings: &Settings, library: &Library) -> Self {
+        Self {
+            scope: settings.tracking_scope,
+            matcher: Matcher::new(library),
+            games: library.games().iter().map(Game::key).collect(),
+            allowlist: settings
+                .allowlist
+                .iter()
+                .map(|process| match Path::new(process).is_absolute() {
+                    true => process.clone(),
+                    false => process.to_lowercase(),
+                })
+                .collect(),
+            use_denylist: settings.use_denylist,
+        }
+    }
+
+    /// The key to record `process` under: the game it matches, or its own
+    /// key if that is in scope. `launched` is the key of the game whose
+    /// launched process tree contains it.
+    pub fn key_for(&self, process: &ProcessInfo, launched: Option<&str>) -> Option<String> {
+        match self.matcher.game_for(process, launched) {
+            Some(game) => Some(game.to_string()),
+            None if self.tracks(&process.key) => Some(process.key.clone()),
+            None => None,
+        }
+    }
+
+    /// Whether to record the process with tracker key `key`. Allowlist and
+    /// denylist entries match its file name.
+    pub fn tracks(&self, key: &str) -> bool {
+        if self.games.contains(key) {
+            return true;
+        }
+
+        match self.scope {
+            TrackingScope::Library => false,
+            TrackingScope::Allowlist => {
+                self.allowlist.contains(key) || self.allowlist.contains(&process_name(key))
+            }
+            TrackingScope::All => !(self.use_denylist && is_denied(&process_name(key))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::fixtures::{game, process};
+    use crate::core::matching::MatchRule;
+    use crate::core::session::History;
+    use crate::core::target::LaunchTarget;
+
+    const GAME: &str = "steam://rungameid/504230";
+
+    fn filter(scope: TrackingScope, use_denylist: bool) -> Filter {
+        let settings = Settings {
+            tracking_scope: scope,
+            allowlist: vec!["Firefox".to_string(), "/opt/tool/run".to_string()],
+            use_denylist,
+            ..Default::default()
+        };
+
+        let mut library = Library::default();
+
+        library
+            .add(Game {
+                rules: vec![MatchRule::Descendants],
+                ..game("Celeste", LaunchTarget::Steam(504230))
+            })
+            .unwrap();
+
+        Filter::new(&settings, &library)
+    }
+
+    #[test]
+    fn denylist_matches_names_and_prefixes() {
+        assert!(is_denied("kworker/0:1-events"));
+        assert!(is_denied("svchost.exe"));
+        assert!(!is_denied("svchost.exe.bak"));
+        assert!(!is_denied("celeste"));
+    }
+
+    #[test]
+    fn scopes_decide_what_is_tracked() {
+        let keys = [
+            GAME,
+            "/usr/bin/firefox",
+            "/opt/tool/run",
+            "/usr/bin/kthreadd",
+            "/usr/bin/vim",
+        ];
+
+        let tracked = |filter: Filter| -> Vec<&str> {
+            keys.iter()
+                .copied()
+                .filter(|key| filter.tracks(key))
+                .collect()
+        };
+
+        assert_eq!(tracked(filter(TrackingScope::Library, true)), [GAME]);
+        assert_eq!(
+            tracked(filter(TrackingScope::Allowlist, true)),
+            [GAME, "/usr/bin/firefox", "/opt/tool/run"]
+        );
+        assert_eq!(
+            tracked(filter(TrackingScope::All, true)),
+            [GAME, "/usr/bin/firefox", "/opt/tool/run", "/usr/bin/vim"]
+        );
+        assert_eq!(tracked(filter(TrackingScope::All, false)), keys);
+    }
+
+    #[test]
+    fn games_are_recorded_under_their_key() {
+        let filter = filter(TrackingScope::Library, true);
+
+        let steam = process(
+            1,
+            None,
+            "/games/celeste/Celeste",
+            "/games/celeste/Celeste AppId=504230",
+        );
+        let vim = process(1, None, "/usr/bin/vim", "/usr/bin/vim");
+
+        assert_eq!(filter.key_for(&steam, None).as_deref(), Some(GAME));
+        assert_eq!(filter.key_for(&vim, None), None);
+        // started by the game, for its descendants rule
+        assert_eq!(filter.key_for(&vim, Some(GAME)).as_deref(), Some(GAME));
+    }
+
+    #[test]
+    fn prune_keeps_what_is_in_scope() {
+        let filter = filter(TrackingScope::Allowlist, true);
+        let mut history = History::default();
+
+        for key in [GAME, "/usr/bin/firefox", "/usr/bin/vim"] {
+            history.record_for_test(key, 100, 60);
+        }
+
+        assert_eq!(history.prune(|process| filter.tracks(process)), 1);
+        assert_eq!(history.total(GAME), 60);
+        assert_eq!(history.total("/usr/bin/firefox"), 60);
+        assert_eq!(history.total("/usr/bin/vim"), 0);
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+
+use crate::core::clock::Tick;
+
+/// Days the sessions of processes outside the library are kept before
+/// `History::compact` folds them into their totals.
+pub const COMPACT_DAYS: u64 = 30;
+
+/// One run of a process, from when the tracker first saw it to when it was
+/// last seen. Times are unix timestamps in seconds. `seconds` is the time
+/// actually credited, which can be less than `end - start` if the tracker
+/// stalled.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Session {
+    pub process: String,
+    pub pid: u32,
+    pub start: u64,
+    pub end: u64,
+    pub seconds: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    /// The signal that killed a launched game, on Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+}
+
+impl Session {
+    /// The share of `seconds` that falls between `from` and `to`.
+    fn seconds_between(&self, from: u64, to: u64) -> u64 {
+        let start = self.start.max(from);
+        let end = self.end.min(to);
+
+        if self.start == self.end {
+            return if (from..to).contains(&self.start) {
+                self.seconds
+            } else {
+                0
+            };
+        }
+
+        if start >= end {
+            return 0;
+        }
+
+        (self.seconds as u128 * (end - start) as u128 / (self.end - self.start) as u128) as u64
+    }
+}
+
+/// Every recorded session plus the ones still running. A process is running
+/// from when the first PID with its name appears until the last one exits.
+#[derive(Default, Clone, Debug)]
+pub struct History {
+    sessions: Vec<Session>,
+    running: HashMap<String, Session>,
+
+    /// Totals recorded before sessions existed, with no timestamps.
+    imported: HashMap<String, u64>,
+}
+
+impl History {
+    pub fn new(sessions: Vec<Session>, imported: HashMap<String, u64>) -> Self {
+        Self {
+            sessions,
+            running: HashMap::new(),
+            imported,
+        }
+    }
+
+    /// Updates sessions from one scan of the running processes. New names
+    /// open a session, known ones are credited `tick.credit` seconds and
+    /// missing ones are closed. A gap closes everything first.
+    pub fn observe(&mut self, tick: Tick, seen: impl IntoIterator<Item = (u32, String)>) {
+        if tick.gap {
+            for (_, mut session) in self.running.drain() {
+                // the credited time is put right after the last scan, so
+                // end - start still covers seconds
+                session.seconds += tick.credit;
+                session.end = (session.end + tick.credit).min(tick.now);
+
+                self.sessions.push(session);
+            }
+        }
+
+        let mut alive = HashSet::new();
+
+        for (pid, process) in seen {
+            if !alive.insert(process.clone()) {
+                continue;
+            }
+
+            self.running
+                .entry(process)
+                .and_modify(|session| {
+                    session.end = tick.now;
+                    session.seconds += tick.credit;
+                })
+                .or_insert_with_key(|process| Session {
+                    process: process.clone(),
+                    pid,
+                    start: tick.now,
+                    end: tick.now,
+                    seconds: 0,
+                    exit_status: None,
+                    exit_signal: None,
+                });
+        }
+
+        let gone: Vec<String> = self
+            .running
+            .keys()
+            .filter(|process| !alive.contains(*process))
+            .cloned()
+            .collect();
+
+        for process in gone {
+            if let Some(session) = self.running.remove(&process) {
+                self.sessions.push(session);
+            }
+        }
+    }
+
+    /// Records `process` running for `seconds` from `start` the way the
+    /// tracker sees it: appearing, credited once and gone. Anything else
+    /// still running is closed too.
+    #[cfg(test)]
+    pub fn record_for_test(&mut self, process: &str, start: u64, seconds: u64) {
+        let seen = [(1, process.to_string())];
+        let tick = |now, credit| Tick {
+            now,
+            credit,
+            gap: false,
+        };
+
+        self.observe(tick(start, 0), seen.clone());
+        self.observe(tick(start + seconds, seconds), seen);
+        self.observe(tick(start + seconds, 0), []);
+    }
+
+    /// Records how the launched process `pid` of `process` exited, on its
+    /// session or else the latest one of `process`.
+    pub fn record_exit(&mut self, process: &str, pid: u32, code: Option<i32>, signal: Option<i32>) {
+        // closed sessions are appended as they end, so the last is latest
+        let session = match self.running.get_mut(process) {
+            Some(session) => Some(session),
+            None => self
+                .sessions
+                .iter()
+                .rposition(|session| session.process == process && session.pid == pid)
+                .or_else(|| {
+                    self.sessions
+                        .iter()
+                        .rposition(|session| session.process == process)
+                })
+                .map(|index| &mut self.sessions[index]),
+        };
+
+        if let Some(session) = session {
+            session.exit_status = code;
+            session.exit_signal = signal;
+        }
+    }
+
+    /// Switches to `saved`, read back after another program changed the
+    /// library file. Sessions running here stay open, and the copies of them
+    /// saved earlier are dropped.
+    pub fn reload(&mut self, saved: History) {
+        let running = std::mem::take(&mut self.running);
+
+        *self = saved;
+
+        self.sessions.retain(|session| {
+            !running
+                .get(&session.process)
+                .is_some_and(|open| open.pid == session.pid && open.start == session.start)
+        });
+
+        self.running = running;
+    }
+
+    /// Finished and running sessions.
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.iter().chain(self.running.values())
+    }
+
+    pub fn imported(&self) -> &HashMap<String, u64> {
+        &self.imported
+    }
+
+    /// Deletes all sessions and imported time of processes `keep` rejects.
+    /// Returns how many processes were removed.
+    pub fn prune(&mut self, keep: impl Fn(&str) -> bool) -> usize {
+        let mut removed = HashSet::new();
+
+        for session in self.sessions() {
+            if !keep(&session.process) {
+                removed.insert(session.process.clone());
+            }
+        }
+
+        for process in self.imported.keys() {
+            if !keep(process) {
+                removed.insert(process.clone());
+            }
+        }
+
+        self.sessions
+            .retain(|session| !removed.contains(&session.process));
+        self.running.retain(|process, _| !removed.contains(process));
+        self.imported
+            .retain(|process, _| !removed.contains(process));
+
+        removed.len()
+    }
+
+    /// Folds the sessions of processes `keep` rejects that ended before
+    /// `before` into their imported time, so tracking every process doesn't
+    /// grow the history forever. Their totals stay the same. Returns how many
+    /// sessions were folded.
+    pub fn compact(&mut self, keep: impl Fn(&str) -> bool, before: u64) -> usize {
+        let count = self.sessions.len();
+        let imported = &mut self.imported;
+
+        self.sessions.retain(|session| {
+            if session.end >= before || keep(&session.process) {
+                return true;
+            }
+
+            *imported.entry(session.process.clone()).or_default() += session.seconds;
+
+            false
+        });
+
+        count - self.sessions.len()
+    }
+
+    /// Moves all time of `from` over to `to`, for a game whose key changed.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if from == to {
+            return;
+        }
+
+        for session in &mut self.sessions {
+            if session.process == from {
+                to.clone_into(&mut session.process);
+            }
+        }
+
+        if let Some(mut session) = self.running.remove(from) {
+            to.clone_into(&mut session.process);
+
+            // both running, keep the newer one open
+            if let Some(other) = self.running.insert(to.to_string(), session) {
+                self.sessions.push(other);
+            }
+        }
+
+        if let Some(seconds) = self.imported.remove(from) {
+            *self.imported.entry(to.to_string()).or_default() += seconds;
+        }
+    }
+
+    /// Takes all sessions and imported time of `process` out, for a game
+    /// moved to the trash. A running session is closed.
+    pub fn take(&mut self, process: &str) -> (Vec<Session>, u64) {
+        let mut taken = Vec::new();
+
+        self.sessions.retain(|session| {
+            if session.process == process {
+                taken.push(session.clone());
+            }
+
+            session.process != process
+        });
+
+        taken.extend(self.running.remove(process));
+
+        (taken, self.imported.remove(process).unwrap_or(0))
+    }
+
+    /// Puts back what `take` took.
+    pub fn put_back(&mut self, process: &str, sessions: Vec<Session>, imported: u64) {
+        self.sessions.extend(sessions);
+
+        // keep them in the order they ended, see record_exit
+        self.sessions.sort_by_key(|session| session.end);
+
+        if imported > 0 {
+            *self.imported.entry(process.to_string()).or_default() += imported;
+        }
+    }
+
+    pub fn is_running(&self, process: &str) -> bool {
+        self.running.contains_key(process)
+    }
+
+    /// Total seconds per process.
+    pub fn totals(&self) -> HashMap<String, u64> {
+        let mut totals = self.imported.clone();
+
+        for session in self.sessions() {
+            *totals.entry(session.process.clone()).or_default() += session.seconds;
+        }
+
+        totals
+    }
+
+    pub fn total(&self, process: &str) -> u64 {
+        let recorded: u64 = self
+            .sessions()
+            .filter(|session| session.process == process)
+            .map(|session| session.seconds)
+            .sum();
+
+        self.imported.get(process).unwrap_or(&0) + recorded
+    }
+
+    /// Seconds `process` ran between the `from` and `to` timestamps.
+    pub fn between(&self, process: &str, from: u64, to: u64) -> u64 {
+        self.sessions()
+            .filter(|session| session.process == process)
+            .map(|session| session.seconds_between(from, to))
+            .sum()
+    }
+
+    pub fn last_played(&self, process: &str) -> Option<u64> {
+        self.sessions()
+            .filter(|session| session.process == process)
+            .map(|session| session.end)
+            .max()
+    }
+
+    /// Seconds played since Monday in local time.
+    pub fn this_week(&self, process: &str) -> u64 {
+        let today = Local::now().date_naive();
+        let monday = today - chrono::Days::new(today.weekday().num_days_from_monday().into());
+
+        self.between(process, day_start(monday), u64::MAX)
+    }
+
+    /// Seconds played on each of the last `days` local days, oldest first.
+    pub fn per_day(&self, process: &str, days: u64) -> Vec<(NaiveDate, u64)> {
+        let today = Local::now().date_naive();
+
+        (0..days)
+            .rev()
+            .map(|ago| {
+                let day = today - chrono::Days::new(ago);
+                let next = day + chrono::Days::new(1);
+
+                (day, self.between(process, day_start(day), day_start(next)))
+            })
+            .collect()
+    }
+}
+
+/// Unix timestamp of local midnight starting `day`.
+pub fn day_start(day: NaiveDate) -> u64 {
+    Local
+        .from_local_datetime(&day.and_time(chrono::NaiveTime::MIN))
+        .earliest()
+        .map(|time| time.timestamp().max(0) as u64)
+        .unwrap_or(0)
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(now: u64, credit: u64) -> Tick {
+        Tick {
+            now,
+            credit,
+            gap: false,
+        }
+    }
+
+    #[test]
+    fn reload_keeps_running_sessions() {
+        let mut history = History::default();
+
+        history.observe(tick(100, 0), [(1, "game".to_string())]);
+        history.observe(tick(105, 5), [(1, "game".to_string())]);
+
+        // saved with the running session closed, then another program
+        // added time of its own
+        let mut imported = history.imported().clone();
+
+        imported.insert("other".to_string(), 60);
+
+        let saved = History::new(history.sessions().cloned().collect(), imported);
+
+        history.reload(saved);
+        history.observe(tick(110, 5), [(1, "game".to_string())]);
+
+        assert!(history.is_running("game"));
+        assert_eq!(history.sessions().count(), 1);
+        assert_eq!(history.total("game"), 10);
+        assert_eq!(history.total("other"), 60);
+    }
+
+    #[test]
+    fn gaps_split_sessions() {
+        let mut history = History::default();
+
+        history.observe(tick(100, 0), [(1, "game".to_string())]);
+        history.observe(tick(105, 5), [(1, "game".to_string())]);
+
+        // suspended for an hour, at most one interval is credited
+        let gap = Tick {
+            now: 3705,
+            credit: 5,
+            gap: true,
+        };
+
+        history.observe(gap, [(1, "game".to_string())]);
+        history.observe(tick(3710, 5), [(1, "game".to_string())]);
+
+        let sessions: Vec<Session> = history.sessions().cloned().collect();
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!((sessions[0].start, sessions[0].seconds), (100, 10));
+        assert_eq!(sessions[0].end, 110);
+        assert_eq!((sessions[1].start, sessions[1].seconds), (3705, 5));
+        assert_eq!(history.total("game"), 15);
+    }
+
+    #[test]
+    fn compact_keeps_totals() {
+        let mut history = History::default();
+
+        for (start, process) in [(100, "game"), (100, "editor"), (5000, "editor")] {
+            history.record_for_test(process, start, 60);
+        }
+
+        history.observe(tick(6000, 0), [(1, "editor".to_string())]);
+
+        assert_eq!(history.compact(|process| process == "game", 1000), 1);
+
+        // the game and the recent and running sessions are kept
+        assert_eq!(history.sessions().count(), 3);
+        assert_eq!(history.imported().get("editor"), Some(&60));
+        assert_eq!(history.total("editor"), 120);
+        assert_eq!(history.total("game"), 60);
+    }
+}
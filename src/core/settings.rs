@@ -0,0 +1,228 @@
+use crate::core::budget::Budget;
+use crate::core::lock::Lock;
+use crate::core::runner::Runner;
+use crate::core::tracker::DEFAULT_INTERVAL;
+use crate::core::triggers::{ClassHours, Event};
+use crate::core::wrappers::Wrapper;
+
+/// Which processes the tracker records.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackingScope {
+    /// Only games in the library.
+    Library,
+    /// Library games plus the processes in `Settings::allowlist`.
+    Allowlist,
+    /// Every process, minus the built-in denylist if enabled.
+    #[default]
+    All,
+}
+
+impl TrackingScope {
+    pub const ALL: [TrackingScope; 3] = [
+        TrackingScope::Library,
+        TrackingScope::Allowlist,
+        TrackingScope::All,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TrackingScope::Library => "Library games",
+            TrackingScope::Allowlist => "Games and allowlist",
+            TrackingScope::All => "All processes",
+        }
+    }
+}
+
+/// What the PANIC button does to running games.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PanicMode {
+    /// Terminate every game process tree.
+    #[default]
+    Kill,
+    /// Freeze the game process trees until they are resumed.
+    Suspend,
+    /// Leave games running and only hide the launcher.
+    Hide,
+    /// Leave games running and open `Settings::decoy`.
+    Decoy,
+}
+
+impl PanicMode {
+    pub const ALL: [PanicMode; 4] = [
+        PanicMode::Kill,
+        PanicMode::Suspend,
+        PanicMode::Hide,
+        PanicMode::Decoy,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PanicMode::Kill => "Kill games",
+            PanicMode::Suspend => "Suspend games",
+            PanicMode::Hide => "Hide launcher only",
+            PanicMode::Decoy => "Open decoy",
+        }
+    }
+}
+
+/// Where games without their own working directory are started.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkingDirPolicy {
+    /// The folder holding the executable, where most games look for their
+    /// data.
+    #[default]
+    Executable,
+    /// Wherever the launcher itself was started.
+    Launcher,
+    Home,
+}
+
+impl WorkingDirPolicy {
+    pub const ALL: [WorkingDirPolicy; 3] = [
+        WorkingDirPolicy::Executable,
+        WorkingDirPolicy::Launcher,
+        WorkingDirPolicy::Home,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WorkingDirPolicy::Executable => "Folder of the executable",
+            WorkingDirPolicy::Launcher => "Folder the launcher runs in",
+            WorkingDirPolicy::Home => "Home folder",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Follow the desktop.
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::System => "System",
+            Theme::Light => "Light",
+            Theme::Dark => "Dark",
+        }
+    }
+}
+
+/// The page the window opens on.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupPage {
+    /// Whichever page was open when the window closed.
+    #[default]
+    Last,
+    Home,
+    Launch,
+    AddGame,
+    ProcessTime,
+    Settings,
+}
+
+impl StartupPage {
+    pub const ALL: [StartupPage; 6] = [
+        StartupPage::Last,
+        StartupPage::Home,
+        StartupPage::Launch,
+        StartupPage::AddGame,
+        StartupPage::ProcessTime,
+        StartupPage::Settings,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StartupPage::Last => "Last open page",
+            StartupPage::Home => "Home",
+            StartupPage::Launch => "Launch",
+            StartupPage::AddGame => "Add Game",
+            StartupPage::ProcessTime => "Process Time",
+            StartupPage::Settings => "Settings",
+        }
+    }
+}
+
+/// Preferences saved alongside the library in `library.json`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Seconds between process scans.
+    pub scan_interval_secs: u64,
+    pub tracking_scope: TrackingScope,
+    /// Lowercase process names or executable paths tracked in
+    /// `TrackingScope::Allowlist`.
+    pub allowlist: Vec<String>,
+    /// Skip system and kernel processes in `TrackingScope::All`.
+    pub use_denylist: bool,
+    /// Seconds games get to exit after SIGTERM on panic before they are
+    /// killed.
+    pub panic_grace_secs: u64,
+    pub panic_mode: PanicMode,
+    /// Program or document opened by `PanicMode::Decoy`.
+    pub decoy: String,
+    /// Keep the launcher window open after panic instead of exiting.
+    pub keep_open: bool,
+    /// Processes that fire panic when they start, matched like the
+    /// allowlist.
+    pub panic_processes: Vec<String>,
+    /// Weekly time windows that fire panic when they begin.
+    pub class_hours: Vec<ClassHours>,
+    /// One-off time windows that fire panic when they begin.
+    pub events: Vec<Event>,
+    /// Wrapper profiles, global ones applied in this order.
+    pub wrappers: Vec<Wrapper>,
+    /// Wine and Proton builds games can be run through.
+    pub runners: Vec<Runner>,
+    pub working_dir_policy: WorkingDirPolicy,
+    pub theme: Theme,
+    pub startup_page: StartupPage,
+    /// Playtime budget for all games together.
+    pub budget: Budget,
+    /// Minutes left at which running games are warned about their budget.
+    pub budget_warnings: Vec<u64>,
+    /// Stop a game when its budget runs out, on top of refusing to launch
+    /// it.
+    pub budget_kill: bool,
+    /// PIN or passphrase needed to change settings, edit or remove games and
+    /// change recorded time.
+    pub lock: Lock,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scan_interval_secs: DEFAULT_INTERVAL.as_secs(),
+            tracking_scope: TrackingScope::default(),
+            allowlist: Vec::new(),
+            use_denylist: true,
+            panic_grace_secs: 3,
+            panic_mode: PanicMode::default(),
+            decoy: String::new(),
+            keep_open: false,
+            panic_processes: Vec::new(),
+            class_hours: Vec::new(),
+            events: Vec::new(),
+            wrappers: Vec::new(),
+            runners: Vec::new(),
+            working_dir_policy: WorkingDirPolicy::default(),
+            theme: Theme::default(),
+            startup_page: StartupPage::default(),
+            budget: Budget::default(),
+            budget_warnings: vec![10, 1],
+            budget_kill: false,
+            lock: Lock::default(),
+        }
+    }
+}
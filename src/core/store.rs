@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::core::{Error, Result};
+
+/// Reads the RON key-value file eframe persists app state in. Only used to
+/// migrate libraries saved before `library.json` existed.
+pub struct Store {
+    path: PathBuf,
+    kv: HashMap<String, String>,
+}
+
+impl Store {
+    /// Opens the store at `path`. A missing file is treated as empty.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let kv = match std::fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).map_err(|err| Error::Storage(err.to_string()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self { path, kv })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.kv.get(key).and_then(|value| ron::from_str(value).ok())
+    }
+}
@@ -0,0 +1,347 @@
+//! What launching a game starts: an executable, or something that starts the
+//! game for us like Steam or an emulator.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::core::tracker::process_key;
+use crate::core::{Error, Result};
+
+/// Saved like a `MatchRule`, `{ "kind": "steam", "value": 504230 }`. Plain
+/// paths from before targets existed load as `Executable`.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(
+    remote = "Self",
+    tag = "kind",
+    content = "value",
+    rename_all = "snake_case"
+)]
+pub enum LaunchTarget {
+    Executable(PathBuf),
+    /// Run by `sh -c`, or `cmd /C` on Windows. Launch arguments are added to
+    /// the end as `"$@"`, so with arguments a pipe or `&&` chain only passes
+    /// them to its last command; without any the command runs as written.
+    Shell(String),
+    /// Opened with the default application, like `xdg-open`.
+    Url(String),
+    /// A Flatpak application id, like `com.valvesoftware.Steam`.
+    Flatpak(String),
+    /// A Steam app id, started through `steam://rungameid/`.
+    Steam(u32),
+    Emulator {
+        emulator: PathBuf,
+        rom: PathBuf,
+    },
+}
+
+impl<'de> serde::Deserialize<'de> for LaunchTarget {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        // not an untagged enum, which ron can't read the tagged form through
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = LaunchTarget;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a path or a launch target")
+            }
+
+            fn visit_str<E: serde::de::Error>(
+                self,
+                path: &str,
+            ) -> std::result::Result<Self::Value, E> {
+                Ok(LaunchTarget::Executable(path.into()))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                LaunchTarget::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl serde::Serialize for LaunchTarget {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        LaunchTarget::serialize(self, serializer)
+    }
+}
+
+impl Default for LaunchTarget {
+    fn default() -> Self {
+        LaunchTarget::Executable(PathBuf::new())
+    }
+}
+
+impl LaunchTarget {
+    pub const KINDS: [&'static str; 6] =
+        ["executable", "shell", "url", "flatpak", "steam", "emulator"];
+
+    /// `rom` is only used by emulators.
+    pub fn new(kind: &str, value: &str, rom: &str) -> Result<Self> {
+        let value = value.trim();
+
+        let target =
+            match kind {
+                "executable" => LaunchTarget::Executable(value.into()),
+                "shell" => LaunchTarget::Shell(value.to_string()),
+                "url" => LaunchTarget::Url(value.to_string()),
+                "flatpak" => LaunchTarget::Flatpak(value.to_string()),
+                "steam" => LaunchTarget::Steam(value.parse().map_err(|_| {
+                    Error::InvalidTarget(format!("{} is not a Steam app id", value))
+                })?),
+                "emulator" => LaunchTarget::Emulator {
+                    emulator: value.into(),
+                    rom: rom.trim().into(),
+                },
+                _ => return Err(Error::InvalidTarget(format!("unknown kind {}", kind))),
+            };
+
+        Ok(target)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LaunchTarget::Executable(_) => "executable",
+            LaunchTarget::Shell(_) => "shell",
+            LaunchTarget::Url(_) => "url",
+            LaunchTarget::Flatpak(_) => "flatpak",
+            LaunchTarget::Steam(_) => "steam",
+            LaunchTarget::Emulator { .. } => "emulator",
+        }
+    }
+
+    /// The `value` and `rom` that `new` makes this target from.
+    pub fn parts(&self) -> (String, String) {
+        match self {
+            LaunchTarget::Executable(path) => (path.to_string_lossy().into_owned(), String::new()),
+            LaunchTarget::Shell(value)
+            | LaunchTarget::Url(value)
+            | LaunchTarget::Flatpak(value) => (value.clone(), String::new()),
+            LaunchTarget::Steam(id) => (id.to_string(), String::new()),
+            LaunchTarget::Emulator { emulator, rom } => (
+                emulator.to_string_lossy().into_owned(),
+                rom.to_string_lossy().into_owned(),
+            ),
+        }
+    }
+
+    /// Checks the target can be launched, the same way for every caller.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            LaunchTarget::Executable(path) if !path.exists() => {
+                Err(Error::MissingExecutable(path.clone()))
+            }
+            LaunchTarget::Executable(_) => Ok(()),
+            LaunchTarget::Shell(command) if command.trim().is_empty() => {
+                Err(Error::InvalidTarget("the command is empty".to_string()))
+            }
+            LaunchTarget::Shell(_) => Ok(()),
+            LaunchTarget::Url(url) if !is_url(url) => {
+                Err(Error::InvalidTarget(format!("{} is not a URL", url)))
+            }
+            LaunchTarget::Url(_) => Ok(()),
+            LaunchTarget::Flatpak(id) => {
+                // reverse DNS with at least three parts, see the flatpak docs
+                let parts: Vec<&str> = id.split('.').collect();
+
+                let valid = parts.len() >= 3
+                    && parts.iter().all(|part| {
+                        !part.is_empty()
+                            && !part.starts_with(|c: char| c.is_ascii_digit())
+                            && part
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    });
+
+                match valid {
+                    true => Ok(()),
+                    false => Err(Error::InvalidTarget(format!(
+                        "{} is not a Flatpak app id",
+                        id
+                    ))),
+                }
+            }
+            LaunchTarget::Steam(0) => {
+                Err(Error::InvalidTarget("0 is not a Steam app id".to_string()))
+            }
+            LaunchTarget::Steam(_) => Ok(()),
+            LaunchTarget::Emulator { emulator, rom } => {
+                if !emulator.exists() {
+                    Err(Error::MissingExecutable(emulator.clone()))
+                } else if !rom.is_file() {
+                    Err(Error::InvalidTarget(format!(
+                        "ROM {} does not exist",
+                        rom.display()
+                    )))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// The key the tracker records the game's time under. Executables use
+    /// their path like any process; an emulated game uses its ROM so each
+    /// game on the same emulator is kept apart.
+    pub fn key(&self) -> String {
+        match self {
+            LaunchTarget::Executable(path) => process_key(path),
+            LaunchTarget::Emulator { rom, .. } => process_key(rom),
+            _ => self.to_string(),
+        }
+    }
+
+    /// The file run for the game, if it is one.
+    pub fn executable(&self) -> Option<&Path> {
+        match self {
+            LaunchTarget::Executable(path) => Some(path),
+            LaunchTarget::Emulator { emulator, .. } => Some(emulator),
+            _ => None,
+        }
+    }
+
+    /// Whether launch arguments are passed on. URLs and Steam games are
+    /// started through another program that doesn't forward them.
+    pub fn takes_args(&self) -> bool {
+        !matches!(self, LaunchTarget::Url(_) | LaunchTarget::Steam(_))
+    }
+
+    /// Whether the target is handed to the system opener, like `xdg-open`,
+    /// which returns right away. The game isn't a child of the launcher then.
+    pub fn through_opener(&self) -> bool {
+        matches!(self, LaunchTarget::Url(_) | LaunchTarget::Steam(_))
+    }
+}
+
+impl fmt::Display for LaunchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchTarget::Executable(path) => write!(f, "{}", path.display()),
+            LaunchTarget::Shell(command) => write!(f, "shell:{}", command),
+            LaunchTarget::Url(url) => write!(f, "{}", url),
+            LaunchTarget::Flatpak(id) => write!(f, "flatpak:{}", id),
+            LaunchTarget::Steam(id) => write!(f, "steam://rungameid/{}", id),
+            LaunchTarget::Emulator { emulator, rom } => {
+                write!(f, "{} {}", emulator.display(), rom.display())
+            }
+        }
+    }
+}
+
+/// The reverse of `Display`, except for emulators which need their ROM
+/// given separately: `steam:<id>`, `flatpak:<id>` and `shell:<command>`
+/// prefixes, URLs, otherwise a path.
+impl FromStr for LaunchTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+
+        if let Some(id) = s
+            .strip_prefix("steam://rungameid/")
+            .or_else(|| s.strip_prefix("steam:"))
+        {
+            return LaunchTarget::new("steam", id, "");
+        }
+
+        if let Some(id) = s.strip_prefix("flatpak:") {
+            return LaunchTarget::new("flatpak", id, "");
+        }
+
+        if let Some(command) = s.strip_prefix("shell:") {
+            return LaunchTarget::new("shell", command, "");
+        }
+
+        match is_url(s) {
+            true => Ok(LaunchTarget::Url(s.to_string())),
+            false => Ok(LaunchTarget::Executable(s.into())),
+        }
+    }
+}
+
+/// `scheme://rest`. Single letter schemes are left out so they can't be
+/// mistaken for Windows drives.
+fn is_url(s: &str) -> bool {
+    match s.split_once("://") {
+        Some((scheme, rest)) => {
+            scheme.len() > 1
+                && !rest.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_it_displays() {
+        let targets = [
+            LaunchTarget::Executable("/games/game".into()),
+            LaunchTarget::Shell("game | tee log".to_string()),
+            LaunchTarget::Url("https://example.com/play".to_string()),
+            LaunchTarget::Flatpak("com.valvesoftware.Steam".to_string()),
+            LaunchTarget::Steam(504230),
+        ];
+
+        for target in targets {
+            assert_eq!(target.to_string().parse::<LaunchTarget>().unwrap(), target);
+        }
+
+        assert_eq!(
+            " steam:504230 ".parse::<LaunchTarget>().unwrap(),
+            LaunchTarget::Steam(504230)
+        );
+        assert_eq!(
+            r"C:\Games\game.exe".parse::<LaunchTarget>().unwrap(),
+            LaunchTarget::Executable(r"C:\Games\game.exe".into())
+        );
+        assert!("steam:celeste".parse::<LaunchTarget>().is_err());
+    }
+
+    #[test]
+    fn validates_targets() {
+        let valid = [
+            LaunchTarget::Shell("echo hi".to_string()),
+            LaunchTarget::Url("https://example.com".to_string()),
+            LaunchTarget::Flatpak("com.valvesoftware.Steam".to_string()),
+            LaunchTarget::Steam(504230),
+        ];
+
+        for target in valid {
+            assert!(target.validate().is_ok(), "{}", target);
+        }
+
+        let invalid = [
+            LaunchTarget::Executable("/no/such/game".into()),
+            LaunchTarget::Shell(" ".to_string()),
+            LaunchTarget::Url("example.com".to_string()),
+            LaunchTarget::Flatpak("steam".to_string()),
+            LaunchTarget::Flatpak("com.1valve.Steam".to_string()),
+            LaunchTarget::Steam(0),
+            LaunchTarget::Emulator {
+                emulator: std::env::current_exe().unwrap(),
+                rom: "/no/such/rom".into(),
+            },
+        ];
+
+        for target in invalid {
+            assert!(target.validate().is_err(), "{}", target);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use crate::core::budget::{Alert, Budgets};
+use crate::core::clock::{Clock, SystemClock, Ticker};
+use crate::core::matching::{Lineage, ProcessInfo};
+use crate::core::scope::Filter;
+use crate::core::session::History;
+use crate::core::triggers::Triggers;
+use crate::core::Game;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type TriggerHandler = Arc<dyn Fn(&[String]) + Send + Sync>;
+
+pub type BudgetHandler = Arc<dyn Fn(&[Alert]) + Send + Sync>;
+
+/// Records a session for every running process the filter allows, on a
+/// background thread. Clones share the thread and its history.
+#[derive(Clone)]
+pub struct Tracker {
+    history: Arc<Mutex<History>>,
+
+    filter: Arc<Mutex<Filter>>,
+
+    /// Launched pids and their game keys, not yet picked up by the thread.
+    launched: Arc<Mutex<Vec<(u32, String)>>>,
+
+    /// Launched process trees, kept up to date by the thread.
+    lineage: Arc<Mutex<Lineage>>,
+
+    /// Milliseconds between scans, shared with the thread.
+    interval: Arc<AtomicU64>,
+
+    triggers: Arc<Mutex<Triggers>>,
+
+    /// Set when the triggers were replaced, so the thread starts over.
+    triggers_changed: Arc<AtomicBool>,
+
+    /// Called from the thread with the labels of triggers that fired.
+    on_trigger: Arc<Mutex<Option<TriggerHandler>>>,
+
+    budgets: Arc<Mutex<Budgets>>,
+
+    /// Called from the thread with budget warnings of running games.
+    on_budget: Arc<Mutex<Option<BudgetHandler>>>,
+
+    /// Scans recorded so far.
+    passes: Arc<AtomicU64>,
+
+    spawned: bool,
+}
+
+impl Default for Tracker {
+    fn default() -> Self {
+        Self::new(History::default())
+    }
+}
+
+impl Tracker {
+    pub fn new(history: History) -> Self {
+        Self {
+            history: Arc::new(Mutex::new(history)),
+            filter: Arc::default(),
+            launched: Arc::default(),
+            lineage: Arc::default(),
+            interval: Arc::new(AtomicU64::new(DEFAULT_INTERVAL.as_millis() as u64)),
+            triggers: Arc::default(),
+            triggers_changed: Arc::default(),
+            on_trigger: Arc::default(),
+            budgets: Arc::default(),
+            on_budget: Arc::default(),
+            passes: Arc::default(),
+            spawned: false,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval.load(Ordering::Relaxed))
+    }
+
+    /// Changes how often processes are scanned. Takes effect after the
+    /// current sleep, also on a running thread.
+    pub fn set_interval(&self, interval: Duration) {
+        let millis = interval.as_millis().clamp(100, u64::MAX.into()) as u64;
+
+        self.interval.store(millis, Ordering::Relaxed);
+    }
+
+    /// Replaces the filter deciding which processes are recorded. Running
+    /// sessions it excludes are closed on the next scan.
+    pub fn set_filter(&self, filter: Filter) {
+        *self.filter.lock().unwrap() = filter;
+    }
+
+    /// Replaces the auto-panic triggers checked on every scan. Triggers that
+    /// already hold when they are replaced don't fire.
+    pub fn set_triggers(&self, triggers: Triggers) {
+        let mut current = self.triggers.lock().unwrap();
+
+        if *current != triggers {
+            *current = triggers;
+
+            self.triggers_changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Sets what to do when triggers fire. Triggers that already hold on
+    /// the first scan don't fire.
+    pub fn on_trigger(&self, handler: impl Fn(&[String]) + Send + Sync + 'static) {
+        *self.on_trigger.lock().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Replaces the playtime budgets checked on every scan.
+    pub fn set_budgets(&self, budgets: Budgets) {
+        *self.budgets.lock().unwrap() = budgets;
+    }
+
+    /// Sets what to do when a running game gets close to or runs out of
+    /// its budget.
+    pub fn on_budget(&self, handler: impl Fn(&[Alert]) + Send + Sync + 'static) {
+        *self.on_budget.lock().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// How many scans were recorded, so views of the history only need
+    /// working out again when it grows.
+    pub fn passes(&self) -> u64 {
+        self.passes.load(Ordering::Relaxed)
+    }
+
+    /// Attributes the process tree started at `pid` to `game`, for its
+    /// `MatchRule::Descendants` rule.
+    pub fn track_launch(&self, pid: u32, game: &Game) {
+        self.launched.lock().unwrap().push((pid, game.key()));
+    }
+
+    /// Pids of every process in the launched trees the thread knows of,
+    /// including descendants whose parent has exited.
+    pub fn launched_pids(&self) -> Vec<u32> {
+        let mut pids: Vec<u32> = self.lineage.lock().unwrap().pids().collect();
+
+        pids.extend(self.launched.lock().unwrap().iter().map(|(pid, _)| *pid));
+
+        pids
+    }
+
+    /// Spawns the tracker thread. Calling this again is a no-op.
+    pub fn start(&mut self) {
+        self.start_with(SystemClock::default());
+    }
+
+    /// Like `start`, but measures time with `clock`.
+    pub fn start_with(&mut self, clock: impl Clock + 'static) {
+        if self.spawned {
+            return;
+        }
+
+        let history = self.history.clone();
+        let filter = self.filter.clone();
+        let launched = self.launched.clone();
+        let lineage = self.lineage.clone();
+        let interval = self.interval.clone();
+        let triggers = self.triggers.clone();
+        let triggers_changed = self.triggers_changed.clone();
+        let on_trigger = self.on_trigger.clone();
+        let budgets = self.budgets.clone();
+        let on_budget = self.on_budget.clone();
+        let passes = self.passes.clone();
+
+        std::thread::spawn(move || {
+            let mut scanner = Scanner::default();
+            let mut ticker = Ticker::new(clock, DEFAULT_INTERVAL);
+            let mut active: Option<HashSet<String>> = None;
+            let mut alerted = HashSet::new();
+
+            loop {
+                let interval = Duration::from_millis(interval.load(Ordering::Relaxed));
+
+                ticker.set_interval(interval);
+
+                // before the scan, so the launched processes are in it
+                for (pid, key) in launched.lock().unwrap().drain(..) {
+                    lineage.lock().unwrap().launched(pid, key);
+                }
+
+                let processes = scanner.scan();
+
+                let mut trees = lineage.lock().unwrap();
+
+                trees.update(&processes);
+
+                let seen: Vec<(u32, String)> = {
+                    let filter = filter.lock().unwrap();
+
+                    processes
+                        .iter()
+                        .filter_map(|process| {
+                            let key = filter.key_for(process, trees.owner(process.pid))?;
+
+                            Some((process.pid, key))
+                        })
+                        .collect()
+                };
+
+                drop(trees);
+
+                // measured after the scan so its cost is credited too
+                let tick = ticker.tick();
+
+                history.lock().unwrap().observe(tick, seen);
+                passes.fetch_add(1, Ordering::Relaxed);
+
+                let alerts =
+                    budgets
+                        .lock()
+                        .unwrap()
+                        .check(&history.lock().unwrap(), tick.now, &mut alerted);
+
+                if triggers_changed.swap(false, Ordering::Relaxed) {
+                    active = None;
+                }
+
+                let now_active = triggers.lock().unwrap().active(&processes, tick.now);
+
+                let fired: Vec<String> = match &active {
+                    Some(active) => now_active.difference(active).cloned().collect(),
+                    None => Vec::new(),
+                };
+
+                active = Some(now_active);
+
+                // no locks are held here, the handler may run the panic routine
+                if !fired.is_empty() {
+                    let handler = on_trigger.lock().unwrap().clone();
+
+                    match handler {
+                        Some(handler) => handler(&fired),
+                        None => log::info!("Triggers fired without a handler: {:?}", fired),
+                    }
+                }
+
+                if !alerts.is_empty() {
+                    let handler = on_budget.lock().unwrap().clone();
+
+                    match handler {
+                        Some(handler) => handler(&alerts),
+                        None => log::info!("Budget alerts without a handler: {:?}", alerts),
+                    }
+                }
+
+                std::thread::sleep(interval);
+            }
+        });
+
+        self.spawned = true;
+    }
+
+    pub fn history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().unwrap()
+    }
+
+    /// Seconds recorded for `game`.
+    pub fn time_for(&self, game: &Game) -> u64 {
+        self.history().total(&game.key())
+    }
+}
+
+/// Lists running processes, refreshing only what the tracker reads instead
+/// of everything `System::refresh_all` collects.
+pub struct Scanner {
+    system: System,
+
+    /// Canonicalized paths of the executables seen in the last scan, so each
+    /// is resolved only once while it runs.
+    keys: HashMap<PathBuf, String>,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            system: System::new(),
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl Scanner {
+    /// Every running process. Its key is the canonical executable path, or
+    /// the lowercase name when the executable can't be read. Executables no
+    /// longer running are forgotten.
+    pub fn scan(&mut self) -> Vec<ProcessInfo> {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            ProcessRefreshKind::new()
+                .with_exe(UpdateKind::OnlyIfNotSet)
+                .with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+
+        let mut keys = HashMap::new();
+
+        let processes = self
+            .system
+            .processes()
+            .iter()
+            .map(|(pid, process)| {
+                let name = process.name().to_string_lossy().to_lowercase();
+                let exe = process.exe().filter(|exe| !exe.as_os_str().is_empty());
+
+                let key = match exe {
+                    Some(exe) => keys
+                        .entry(exe.to_path_buf())
+                        .or_insert_with(|| {
+                            self.keys.remove(exe).unwrap_or_else(|| process_key(exe))
+                        })
+                        .clone(),
+                    None => name.clone(),
+                };
+
+                let cmd: Vec<String> = process
+                    .cmd()
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+
+                ProcessInfo {
+                    pid: pid.as_u32(),
+                    parent: process.parent().map(|pid| pid.as_u32()),
+                    name,
+                    exe: exe.map(Path::to_path_buf),
+                    key,
+                    cmd: cmd.join(" "),
+                    args: cmd.into_iter().skip(1).collect(),
+                }
+            })
+            .collect();
+
+        self.keys = keys;
+
+        processes
+    }
+}
+
+/// The key the tracker stores an executable's time under: its canonical
+/// path, so games with the same file name are kept apart.
+pub fn process_key(location: &Path) -> String {
+    std::fs::canonicalize(location)
+        .unwrap_or_else(|_| location.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The lowercase file name of a key. Keys of processes whose executable
+/// couldn't be read are already names.
+pub fn process_name(key: &str) -> String {
+    let path = Path::new(key);
+
+    match path.file_name() {
+        Some(name) if path.is_absolute() => name.to_string_lossy().to_lowercase(),
+        _ => key.to_string(),
+    }
+}
@@ -0,0 +1,642 @@
+//! Auto-panic: processes and time windows that fire the panic routine when
+//! they start. They are checked by the tracker thread on every scan.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+
+use crate::core::format::format_date;
+use crate::core::matching::ProcessInfo;
+use crate::core::settings::Settings;
+use crate::core::{Error, Result};
+
+/// A weekly time window, like a class period. `start` and `end` are local
+/// `HH:MM` times; an `end` before `start` runs past midnight.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ClassHours {
+    pub name: String,
+    /// Lowercase weekday abbreviations, `mon` to `sun`.
+    pub days: Vec<String>,
+    pub start: String,
+    pub end: String,
+}
+
+impl ClassHours {
+    pub fn new(name: &str, days: &[Weekday], start: &str, end: &str) -> Result<Self> {
+        let hours = Self {
+            name: name.trim().to_string(),
+            days: days
+                .iter()
+                .map(|day| day.to_string().to_lowercase())
+                .collect(),
+            start: start.trim().to_string(),
+            end: end.trim().to_string(),
+        };
+
+        hours.compile()?;
+
+        Ok(hours)
+    }
+
+    fn compile(&self) -> Result<Window> {
+        if self.days.is_empty() {
+            return Err(Error::InvalidSchedule(format!("{} has no days", self.name)));
+        }
+
+        let days = self
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| Error::InvalidSchedule(format!("unknown day {}", day)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Window {
+            days,
+            start: parse_time(&self.start)?,
+            end: parse_time(&self.end)?,
+        })
+    }
+}
+
+/// A one-off time window, usually imported from a calendar.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub name: String,
+    /// Unix timestamps.
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Window {
+    days: HashSet<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Window {
+    fn contains(&self, time: NaiveDateTime) -> bool {
+        let day = time.weekday();
+        let time = time.time();
+
+        if self.start <= self.end {
+            self.days.contains(&day) && self.start <= time && time < self.end
+        } else {
+            (self.days.contains(&day) && time >= self.start)
+                || (self.days.contains(&day.pred()) && time < self.end)
+        }
+    }
+}
+
+/// The auto-panic rules from the settings, ready to check.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Triggers {
+    processes: HashSet<String>,
+    class_hours: Vec<(String, Window)>,
+    events: Vec<Event>,
+}
+
+impl Triggers {
+    /// Class hours that don't parse are skipped; the settings page rejects
+    /// them so this only happens with hand-edited files.
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            processes: settings
+                .panic_processes
+                .iter()
+                .map(|process| match Path::new(process).is_absolute() {
+                    true => process.clone(),
+                    false => process.to_lowercase(),
+                })
+                .collect(),
+            class_hours: settings
+                .class_hours
+                .iter()
+                .filter_map(|hours| match hours.compile() {
+                    Ok(window) => Some((hours.name.clone(), window)),
+                    Err(err) => {
+                        log::warn!("Ignoring class hours {}: {}", hours.name, err);
+                        None
+                    }
+                })
+                .collect(),
+            events: settings.events.clone(),
+        }
+    }
+
+    /// Labels of the triggers that hold at unix time `now` with `processes`
+    /// running. A trigger fires when its label first shows up.
+    pub fn active(&self, processes: &[ProcessInfo], now: u64) -> HashSet<String> {
+        let mut active = HashSet::new();
+
+        for process in processes {
+            if self.processes.contains(&process.key) || self.processes.contains(&process.name) {
+                active.insert(format!("process {}", process.name));
+            }
+        }
+
+        if let Some(time) = Local.timestamp_opt(now as i64, 0).single() {
+            let time = time.naive_local();
+
+            for (name, window) in &self.class_hours {
+                if window.contains(time) {
+                    active.insert(format!("class hours {}", name));
+                }
+            }
+        }
+
+        for event in &self.events {
+            if event.start <= now && now < event.end {
+                active.insert(format!("event {}", event.name));
+            }
+        }
+
+        active
+    }
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M")
+        .map_err(|_| Error::InvalidSchedule(format!("{} is not a HH:MM time", time)))
+}
+
+/// Reads the events of an iCalendar file. Events repeating every week for
+/// good become class hours, others one-off events; a weekly series with
+/// `UNTIL` or `COUNT` becomes one event per time, less its `EXDATE`s. A
+/// series that never ends but has exceptions or skips weeks is refused, and
+/// so is one repeating other than weekly.
+/// All-day events are skipped, and times with a `TZID` are taken as local
+/// time.
+pub fn import_ics(text: &str) -> Result<(Vec<ClassHours>, Vec<Event>)> {
+    // long lines are folded by starting the next one with a space or tab
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut class_hours = Vec::new();
+    let mut events = Vec::new();
+    let mut event: Option<Vec<(String, String)>> = None;
+
+    for line in lines {
+        if line == "BEGIN:VEVENT" {
+            event = Some(Vec::new());
+            continue;
+        }
+
+        if line == "END:VEVENT" {
+            let Some(fields) = event.take() else {
+                continue;
+            };
+
+            let field = |name: &str| {
+                fields
+                    .iter()
+                    .find(|(key, _)| key.split(';').next() == Some(name))
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+            };
+
+            let name = field("SUMMARY")
+                .map(|(_, value)| value)
+                .unwrap_or("Event")
+                .to_string();
+
+            let (Some(start), Some(end)) = (field("DTSTART"), field("DTEND")) else {
+                continue;
+            };
+
+            let (Some(start), Some(end)) = (parse_ics_time(start)?, parse_ics_time(end)?) else {
+                log::info!("Skipping all-day event {}", name);
+                continue;
+            };
+
+            let Some((_, rule)) = field("RRULE") else {
+                events.push(event_at(name, start, end));
+                continue;
+            };
+
+            // importing only the first time of another series would quietly
+            // drop the rest
+            let frequency = rule
+                .split(';')
+                .find_map(|part| part.strip_prefix("FREQ="))
+                .unwrap_or_default();
+
+            if frequency != "WEEKLY" {
+                return Err(Error::InvalidSchedule(format!(
+                    "{} repeats {}, only weekly series can be imported",
+                    name,
+                    match frequency {
+                        "" => "without a frequency".to_string(),
+                        frequency => frequency.to_lowercase(),
+                    }
+                )));
+            }
+
+            let rule = Rule::parse(rule, start, &name)?;
+
+            let excluded = fields
+                .iter()
+                .filter(|(key, _)| key.split(';').next() == Some("EXDATE"))
+                .flat_map(|(key, value)| value.split(',').map(move |value| (key.as_str(), value)))
+                .map(parse_ics_date)
+                .collect::<Result<Vec<_>>>()?;
+
+            if rule.count.is_none() && rule.until.is_none() {
+                // class hours repeat every week, forever
+                if !excluded.is_empty() || rule.interval != 1 {
+                    return Err(Error::InvalidSchedule(format!(
+                        "{} never ends but skips some weeks",
+                        name
+                    )));
+                }
+
+                class_hours.push(ClassHours::new(
+                    &name,
+                    &rule.days,
+                    &start.format("%H:%M").to_string(),
+                    &end.format("%H:%M").to_string(),
+                )?);
+            } else {
+                // a series that ends becomes its single events
+                for time in rule.occurrences(start, &name)? {
+                    let skipped = excluded.iter().any(|exdate| match exdate {
+                        IcsDate::Time(exdate) => *exdate == time,
+                        IcsDate::Day(day) => *day == time.date(),
+                    });
+
+                    if !skipped {
+                        events.push(event_at(name.clone(), time, time + (end - start)));
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        if let (Some(fields), Some((key, value))) = (&mut event, line.split_once(':')) {
+            fields.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    Ok((class_hours, events))
+}
+
+/// Most single events a recurring one is turned into.
+const MAX_OCCURRENCES: usize = 1000;
+
+/// The parts of a weekly `RRULE` that are honoured.
+struct Rule {
+    days: Vec<Weekday>,
+    /// Every how many weeks.
+    interval: u32,
+    count: Option<usize>,
+    until: Option<NaiveDateTime>,
+}
+
+impl Rule {
+    fn parse(rule: &str, start: NaiveDateTime, name: &str) -> Result<Self> {
+        let part = |key: &str| {
+            rule.split(';')
+                .find_map(|part| part.strip_prefix(key)?.strip_prefix('='))
+        };
+
+        let invalid =
+            |part: &str| Error::InvalidSchedule(format!("{} has an invalid {}", name, part));
+
+        let days: Vec<Weekday> = part("BYDAY")
+            .map(|days| days.split(',').filter_map(ics_day).collect())
+            .unwrap_or_else(|| vec![start.weekday()]);
+
+        if days.is_empty() {
+            return Err(Error::InvalidSchedule(format!("{} has no days", name)));
+        }
+
+        let interval = match part("INTERVAL") {
+            Some(interval) => interval
+                .parse()
+                .ok()
+                .filter(|interval| *interval > 0)
+                .ok_or_else(|| invalid("INTERVAL"))?,
+            None => 1,
+        };
+
+        let count = part("COUNT")
+            .map(|count| count.parse().map_err(|_| invalid("COUNT")))
+            .transpose()?;
+
+        let until = part("UNTIL")
+            .map(|until| parse_ics_date(("UNTIL", until)))
+            .transpose()?
+            .map(|until| match until {
+                IcsDate::Time(time) => time,
+                // the whole day is included
+                IcsDate::Day(day) => {
+                    day.and_time(NaiveTime::MIN) + chrono::Duration::seconds(24 * 60 * 60 - 1)
+                }
+            });
+
+        Ok(Self {
+            days,
+            interval,
+            count,
+            until,
+        })
+    }
+
+    /// Start times of the events of a series that ends, from `start` on.
+    /// Exceptions are not left out, they still count towards `COUNT`.
+    fn occurrences(&self, start: NaiveDateTime, name: &str) -> Result<Vec<NaiveDateTime>> {
+        let mut days = self.days.clone();
+
+        days.sort_by_key(|day| day.num_days_from_monday());
+        days.dedup();
+
+        let monday =
+            start.date() - chrono::Days::new(start.weekday().num_days_from_monday().into());
+
+        let mut times = Vec::new();
+
+        for week in (0..).step_by(self.interval as usize) {
+            for day in &days {
+                let time = (monday
+                    + chrono::Days::new(7 * week + u64::from(day.num_days_from_monday())))
+                .and_time(start.time());
+
+                if time < start {
+                    continue;
+                }
+
+                if self.until.is_some_and(|until| time > until)
+                    || self.count.is_some_and(|count| times.len() >= count)
+                {
+                    return Ok(times);
+                }
+
+                if times.len() >= MAX_OCCURRENCES {
+                    return Err(Error::InvalidSchedule(format!(
+                        "{} repeats more than {} times",
+                        name, MAX_OCCURRENCES
+                    )));
+                }
+
+                times.push(time);
+            }
+        }
+
+        Ok(times)
+    }
+}
+
+/// An `EXDATE` or `UNTIL` value.
+enum IcsDate {
+    Time(NaiveDateTime),
+    Day(NaiveDate),
+}
+
+fn parse_ics_date((key, value): (&str, &str)) -> Result<IcsDate> {
+    match parse_ics_time((key, value))? {
+        Some(time) => Ok(IcsDate::Time(time)),
+        None => NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(IcsDate::Day)
+            .map_err(|_| Error::InvalidSchedule(format!("{} is not a date", value))),
+    }
+}
+
+fn event_at(name: String, start: NaiveDateTime, end: NaiveDateTime) -> Event {
+    let timestamp = |time: NaiveDateTime| {
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|time| time.timestamp().max(0) as u64)
+            .unwrap_or(0)
+    };
+
+    Event {
+        name,
+        start: timestamp(start),
+        end: timestamp(end),
+    }
+}
+
+/// A `DTSTART` or `DTEND` as local time, or `None` for a date without a
+/// time.
+fn parse_ics_time((key, value): (&str, &str)) -> Result<Option<NaiveDateTime>> {
+    if (key.contains("VALUE=DATE") && !key.contains("VALUE=DATE-TIME")) || value.len() == 8 {
+        return Ok(None);
+    }
+
+    let invalid = || Error::InvalidSchedule(format!("{} is not a date and time", value));
+
+    match value.strip_suffix('Z') {
+        Some(utc) => {
+            let time =
+                NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+
+            Ok(Some(
+                chrono::Utc
+                    .from_utc_datetime(&time)
+                    .with_timezone(&Local)
+                    .naive_local(),
+            ))
+        }
+        None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .map(Some)
+            .map_err(|_| invalid()),
+    }
+}
+
+fn ics_day(day: &str) -> Option<Weekday> {
+    // BYDAY may carry an ordinal like 1MO, which only applies to monthly rules
+    match day.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Where fired triggers are logged.
+pub fn log_path() -> Option<PathBuf> {
+    crate::core::data::data_dir().map(|dir| dir.join("triggers.log"))
+}
+
+/// Appends `lines` to the log at `path`, each behind the unix time `now`.
+/// Also used for the audit log.
+pub fn append_log(path: &Path, now: u64, lines: &[String]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    for line in lines {
+        writeln!(file, "{} {}", format_date(now), line)?;
+    }
+
+    Ok(())
+}
+
+/// The last `count` lines of the log, newest last.
+pub fn read_log(path: &Path, count: usize) -> Vec<String> {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let lines: Vec<&str> = text.lines().collect();
+
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn calendar(events: &[&str]) -> String {
+        let events: Vec<String> = events
+            .iter()
+            .map(|event| format!("BEGIN:VEVENT\n{}\nEND:VEVENT", event))
+            .collect();
+
+        format!("BEGIN:VCALENDAR\n{}\nEND:VCALENDAR\n", events.join("\n"))
+    }
+
+    #[test]
+    fn parses_class_hours() {
+        let hours =
+            ClassHours::new(" Math ", &[Weekday::Mon, Weekday::Wed], "08:00", "09:30").unwrap();
+
+        assert_eq!(hours.name, "Math");
+        assert_eq!(hours.days, ["mon", "wed"]);
+
+        let window = hours.compile().unwrap();
+
+        // 2024-01-01 is a Monday
+        assert!(window.contains(at("2024-01-01 08:00")));
+        assert!(!window.contains(at("2024-01-01 09:30")));
+        assert!(!window.contains(at("2024-01-02 08:30")));
+
+        assert!(ClassHours::new("Math", &[], "08:00", "09:30").is_err());
+        assert!(ClassHours::new("Math", &[Weekday::Mon], "8am", "09:30").is_err());
+    }
+
+    #[test]
+    fn class_hours_run_past_midnight() {
+        let window = ClassHours::new("Night", &[Weekday::Fri], "22:00", "02:00")
+            .unwrap()
+            .compile()
+            .unwrap();
+
+        // 2024-01-05 is a Friday
+        assert!(window.contains(at("2024-01-05 23:00")));
+        assert!(window.contains(at("2024-01-06 01:00")));
+        assert!(!window.contains(at("2024-01-06 02:00")));
+        assert!(!window.contains(at("2024-01-04 23:00")));
+    }
+
+    #[test]
+    fn imports_class_hours_and_events() {
+        let text = calendar(&[
+            "SUMMARY:Math\nDTSTART;TZID=Europe/Paris:20240101T080000\nDTEND;TZID=Europe/Pa\n ris:20240101T093000\nRRULE:FREQ=WEEKLY;BYDAY=MO,WE",
+            "SUMMARY:Exam\nDTSTART:20240110T140000\nDTEND:20240110T160000",
+            "SUMMARY:Holiday\nDTSTART;VALUE=DATE:20240112\nDTEND;VALUE=DATE:20240113",
+        ]);
+
+        let (class_hours, events) = import_ics(&text).unwrap();
+
+        assert_eq!(
+            class_hours,
+            [ClassHours::new("Math", &[Weekday::Mon, Weekday::Wed], "08:00", "09:30").unwrap()]
+        );
+        assert_eq!(
+            events,
+            [event_at(
+                "Exam".to_string(),
+                at("2024-01-10 14:00"),
+                at("2024-01-10 16:00")
+            )]
+        );
+    }
+
+    #[test]
+    fn series_that_end_become_events() {
+        let text = calendar(&[
+            "SUMMARY:Math\nDTSTART:20240101T080000\nDTEND:20240101T090000\nRRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4\nEXDATE:20240103T080000",
+            "SUMMARY:Art\nDTSTART:20240102T100000\nDTEND:20240102T110000\nRRULE:FREQ=WEEKLY;INTERVAL=2;UNTIL=20240130\nEXDATE;VALUE=DATE:20240116",
+        ]);
+
+        let (class_hours, events) = import_ics(&text).unwrap();
+
+        assert!(class_hours.is_empty());
+
+        // the exceptions still count towards COUNT, UNTIL takes in its day
+        let expected: Vec<Event> = [
+            ("Math", "2024-01-01 08:00"),
+            ("Math", "2024-01-08 08:00"),
+            ("Math", "2024-01-10 08:00"),
+            ("Art", "2024-01-02 10:00"),
+            ("Art", "2024-01-30 10:00"),
+        ]
+        .iter()
+        .map(|(name, start)| {
+            let start = at(start);
+
+            event_at(name.to_string(), start, start + chrono::Duration::hours(1))
+        })
+        .collect();
+
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn refuses_endless_series_with_exceptions() {
+        let exception = calendar(&[
+            "SUMMARY:Math\nDTSTART:20240101T080000\nDTEND:20240101T090000\nRRULE:FREQ=WEEKLY\nEXDATE:20240108T080000",
+        ]);
+        let every_other = calendar(&[
+            "SUMMARY:Math\nDTSTART:20240101T080000\nDTEND:20240101T090000\nRRULE:FREQ=WEEKLY;INTERVAL=2",
+        ]);
+        let too_long = calendar(&[
+            "SUMMARY:Math\nDTSTART:20240101T080000\nDTEND:20240101T090000\nRRULE:FREQ=WEEKLY;COUNT=100000",
+        ]);
+
+        assert!(import_ics(&exception).is_err());
+        assert!(import_ics(&every_other).is_err());
+        assert!(import_ics(&too_long).is_err());
+    }
+
+    #[test]
+    fn refuses_series_that_are_not_weekly() {
+        for rule in ["FREQ=DAILY", "FREQ=MONTHLY;COUNT=3", "COUNT=3"] {
+            let calendar = calendar(&[&format!(
+                "SUMMARY:Math\nDTSTART:20240101T080000\nDTEND:20240101T090000\nRRULE:{}",
+                rule
+            )]);
+
+            assert!(
+                matches!(import_ics(&calendar), Err(Error::InvalidSchedule(_))),
+                "{}",
+                rule
+            );
+        }
+    }
+}
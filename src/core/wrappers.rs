@@ -0,0 +1,246 @@
+//! Wrapper profiles: commands like `gamescope` or `mangohud` that games are
+//! run through. Each profile is a command prefix with its own arguments and
+//! environment, and they stack in order, so `gamemoderun` and `mangohud`
+//! give `gamemoderun mangohud /games/Celeste`.
+
+use std::collections::BTreeMap;
+
+use crate::core::launcher::validate_env_key;
+use crate::core::{Error, Game, Result};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Wrapper {
+    pub name: String,
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Wrap every game, not only those that list it.
+    #[serde(default)]
+    pub global: bool,
+}
+
+impl Wrapper {
+    /// Common wrappers, offered when adding a profile.
+    pub fn presets() -> Vec<Wrapper> {
+        let preset = |name: &str, program: &str, args: &[&str], env: &[(&str, &str)]| Wrapper {
+            name: name.to_string(),
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: env
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            global: false,
+        };
+
+        vec![
+            preset("gamescope", "gamescope", &["-f", "--"], &[]),
+            preset("mangohud", "mangohud", &[], &[]),
+            preset("gamemode", "gamemoderun", &[], &[]),
+            preset("taskset", "taskset", &["-c", "0-3"], &[]),
+        ]
+    }
+
+    /// Checks the profile can be saved next to `others`.
+    pub fn validate(&self, others: &[Wrapper]) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(Error::InvalidWrapper(
+                "a profile requires a name".to_string(),
+            ));
+        }
+
+        if self.program.trim().is_empty() {
+            return Err(Error::InvalidWrapper(format!(
+                "{} has no command",
+                self.name
+            )));
+        }
+
+        if others.iter().any(|other| other.name == self.name) {
+            return Err(Error::InvalidWrapper(format!(
+                "{} already exists",
+                self.name
+            )));
+        }
+
+        for key in self.env.keys() {
+            validate_env_key(key)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The wrappers `game` runs through, outermost first: the global profiles in
+/// the order of `profiles`, then the game's own in its order.
+pub fn resolve<'a>(profiles: &'a [Wrapper], game: &Game) -> Result<Vec<&'a Wrapper>> {
+    let mut wrappers: Vec<&Wrapper> = profiles.iter().filter(|wrapper| wrapper.global).collect();
+
+    for name in &game.wrappers {
+        let wrapper = profiles
+            .iter()
+            .find(|wrapper| &wrapper.name == name)
+            .ok_or_else(|| Error::UnknownWrapper(name.clone()))?;
+
+        if !wrappers.contains(&wrapper) {
+            wrappers.push(wrapper);
+        }
+    }
+
+    Ok(wrappers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::core::fixtures::{game, temp_dir};
+    use crate::core::launcher::CommandLine;
+    use crate::core::runner::{Runner, RunnerKind, WineConfig};
+    use crate::core::settings::WorkingDirPolicy;
+    use crate::core::target::LaunchTarget;
+
+    fn profile(name: &str, args: &[&str], env: &[(&str, &str)], global: bool) -> Wrapper {
+        Wrapper {
+            name: name.to_string(),
+            program: name.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: env
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            global,
+        }
+    }
+
+    fn wrapped(wrappers: &[&str]) -> Game {
+        Game {
+            wrappers: wrappers.iter().map(|name| name.to_string()).collect(),
+            ..game(
+                "Celeste",
+                LaunchTarget::Flatpak("com.celestegame.Celeste".to_string()),
+            )
+        }
+    }
+
+    fn names(wrappers: &[&Wrapper]) -> Vec<String> {
+        wrappers
+            .iter()
+            .map(|wrapper| wrapper.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn globals_go_first() {
+        let profiles = [
+            profile("mangohud", &[], &[], false),
+            profile("gamemoderun", &[], &[], true),
+            profile("gamescope", &["-f", "--"], &[], false),
+            profile("taskset", &["-c", "0-3"], &[], true),
+        ];
+
+        let wrappers = resolve(&profiles, &wrapped(&["gamescope", "mangohud", "taskset"])).unwrap();
+
+        // taskset is global already, so it isn't added twice
+        assert_eq!(
+            names(&wrappers),
+            ["gamemoderun", "taskset", "gamescope", "mangohud"]
+        );
+
+        assert_eq!(
+            names(&resolve(&profiles, &wrapped(&[])).unwrap()),
+            ["gamemoderun", "taskset"]
+        );
+        assert!(matches!(
+            resolve(&profiles, &wrapped(&["missing"])),
+            Err(Error::UnknownWrapper(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn wrappers_nest_around_the_game() {
+        let profiles = [
+            profile(
+                "gamescope",
+                &["-f", "--"],
+                &[("SDL_VIDEODRIVER", "x11")],
+                false,
+            ),
+            profile("mangohud", &[], &[("MANGOHUD", "1")], true),
+        ];
+
+        let mut game = wrapped(&["gamescope"]);
+
+        game.args = vec!["--fullscreen".to_string()];
+        game.env = [
+            ("SDL_VIDEODRIVER".to_string(), Some("wayland".to_string())),
+            ("LD_PRELOAD".to_string(), None),
+        ]
+        .into();
+
+        let wrappers = resolve(&profiles, &game).unwrap();
+        let line = CommandLine::new(
+            &game,
+            &game.args,
+            &wrappers,
+            None,
+            WorkingDirPolicy::Launcher,
+        )
+        .unwrap();
+
+        assert_eq!(line.program, Path::new("mangohud"));
+        assert_eq!(
+            line.to_string(),
+            "env -u LD_PRELOAD MANGOHUD=1 SDL_VIDEODRIVER=wayland \
+             mangohud gamescope -f -- flatpak run com.celestegame.Celeste --fullscreen"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runners_go_between_wrappers_and_the_game() {
+        let dir = temp_dir("wrappers");
+        let exe = dir.join("Game.exe");
+
+        std::fs::write(&exe, "MZ").unwrap();
+
+        let game = Game {
+            location: LaunchTarget::Executable(exe.clone()),
+            wine: Some(WineConfig {
+                runner: "wine".to_string(),
+                prefix: None,
+                env: [("DXVK_HUD".to_string(), "fps".to_string())].into(),
+            }),
+            ..wrapped(&["gamemoderun"])
+        };
+        let runner = Runner {
+            name: "wine".to_string(),
+            kind: RunnerKind::Wine,
+            path: "/opt/wine/bin/wine".into(),
+        };
+        let profiles = [profile("gamemoderun", &[], &[], false)];
+
+        let line = CommandLine::new(
+            &game,
+            &[],
+            &resolve(&profiles, &game).unwrap(),
+            Some((&runner, &dir.join("prefix"))),
+            WorkingDirPolicy::Executable,
+        )
+        .unwrap();
+
+        assert_eq!(
+            line.to_string(),
+            format!(
+                "cd {dir} && env DXVK_HUD=fps WINEPREFIX={dir}/prefix \
+                 gamemoderun /opt/wine/bin/wine {dir}/Game.exe",
+                dir = dir.display()
+            )
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,6 +3,12 @@
 mod app;
 pub use app::GameLunch;
 
+pub mod cli;
+
+pub mod core;
+
 mod enums;
 
 mod structs;
+
+pub const APP_ID: &str = "gamelunch";
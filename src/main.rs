@@ -4,6 +4,12 @@
 fn main() -> eframe::Result {
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        std::process::exit(gamelunch::cli::run(&args));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -15,7 +21,7 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
     eframe::run_native(
-        "gamelunch",
+        gamelunch::APP_ID,
         native_options,
         Box::new(|cc| Ok(Box::new(gamelunch::GameLunch::new(cc)))),
     )
@@ -1,8 +1,68 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Clone, Debug)]
+use uuid::Uuid;
+
+use crate::core::budget::Budget;
+use crate::core::matching::MatchRule;
+use crate::core::runner::WineConfig;
+use crate::core::settings::WorkingDirPolicy;
+use crate::core::target::LaunchTarget;
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Clone, Debug, Default)]
 pub struct Game {
+    /// Stays the same when the game is edited, unlike its name. Nil until
+    /// the game is added to a library.
+    #[serde(default)]
+    pub id: Uuid,
     pub name: String,
     pub author: String,
-    pub location: PathBuf,
+    pub location: LaunchTarget,
+    #[serde(default)]
+    pub rules: Vec<MatchRule>,
+    /// Command line arguments passed on launch.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables to set, or to remove when `None`.
+    #[serde(default)]
+    pub env: BTreeMap<String, Option<String>>,
+    /// Directory to start the game in, see `Game::working_dir`.
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// Names of wrapper profiles to run the game through, outermost first.
+    #[serde(default)]
+    pub wrappers: Vec<String>,
+    /// Runs the game through Wine or Proton, natively when `None`.
+    #[serde(default)]
+    pub wine: Option<WineConfig>,
+    /// Playtime budget of this game, on top of the one for all games.
+    #[serde(default)]
+    pub budget: Budget,
+}
+
+impl Game {
+    /// `working_dir`, or the folder `policy` picks. `None` leaves it to
+    /// the launcher's own.
+    pub fn working_dir(&self, policy: WorkingDirPolicy) -> Option<PathBuf> {
+        if let Some(dir) = &self.working_dir {
+            return Some(dir.clone());
+        }
+
+        match policy {
+            WorkingDirPolicy::Executable => self
+                .location
+                .executable()
+                .and_then(Path::parent)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(Path::to_path_buf),
+            WorkingDirPolicy::Launcher => None,
+            WorkingDirPolicy::Home => dirs::home_dir(),
+        }
+    }
+
+    /// The key the tracker records the game's time under, see
+    /// `LaunchTarget::key`.
+    pub fn key(&self) -> String {
+        self.location.key()
+    }
 }